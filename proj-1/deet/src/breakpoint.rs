@@ -0,0 +1,140 @@
+use crate::dwarf_data::{DwarfData, Line, Type};
+use crate::expression::{self, Expr};
+use nix::unistd::Pid;
+use std::fmt;
+
+/// A user-visible breakpoint. Several breakpoints may share the same address; the Inferior only
+/// keeps track of which addresses currently hold a 0xcc.
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    /// Stable number shown to the user and used by `delete`/`disable`/`enable`
+    pub id: usize,
+    pub addr: usize,
+    /// The location as the user typed it, e.g. `func2`, `12` or `*0x401136`
+    pub spec: String,
+    /// Function and source line the address resolves to, if known
+    pub func: Option<String>,
+    pub line: Option<Line>,
+    pub hit_count: usize,
+    pub enabled: bool,
+    /// Only stop if this holds
+    pub condition: Option<Condition>,
+    /// Number of upcoming hits to skip without stopping
+    pub ignore_count: usize,
+    /// Commands to run whenever this breakpoint stops the inferior, set by `commands`
+    pub commands: Vec<String>,
+    /// Set while the location doesn't resolve to an address, e.g. a function in a shared library
+    /// that hasn't been loaded yet. `addr` is meaningless until it does.
+    pub pending: bool,
+}
+
+impl Breakpoint {
+    pub fn new(
+        id: usize,
+        addr: usize,
+        spec: &str,
+        func: Option<String>,
+        line: Option<Line>,
+    ) -> Breakpoint {
+        Breakpoint {
+            id,
+            addr,
+            spec: spec.to_string(),
+            func,
+            line,
+            hit_count: 0,
+            enabled: true,
+            condition: None,
+            ignore_count: 0,
+            commands: Vec::new(),
+            pending: false,
+        }
+    }
+
+    /// Human-readable description of where this breakpoint is, e.g. `in func2 at foo.c:9`
+    pub fn location(&self) -> String {
+        match (&self.func, &self.line) {
+            (Some(func), Some(line)) => format!("in {} at {}", func, line),
+            (Some(func), None) => format!("in {}", func),
+            (None, Some(line)) => format!("at {}", line),
+            (None, None) => format!("at {}", self.spec),
+        }
+    }
+}
+
+/// A breakpoint condition: an expression such as `i == 3 && p->next`, which holds whenever it
+/// evaluates to something nonzero
+#[derive(Debug, Clone)]
+pub struct Condition {
+    /// The condition as the user typed it
+    pub text: String,
+    pub expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str, debug_data: &DwarfData) -> Result<Condition, String> {
+        Ok(Condition {
+            text: text.to_string(),
+            expr: expression::parse(text, debug_data)?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    /// `watch`: stop when the location is written
+    Write,
+    /// `rwatch`: stop when the location is read
+    Read,
+    /// `awatch`: stop when the location is read or written
+    Access,
+}
+
+/// A hardware watchpoint, occupying one of the x86-64 debug registers DR0-DR3
+#[derive(Debug, Clone)]
+pub struct Watchpoint {
+    /// Shares its numbering with breakpoints
+    pub id: usize,
+    /// What the user asked to watch, e.g. `global` or `*0x404020`
+    pub expression: String,
+    pub addr: usize,
+    pub len: usize,
+    pub kind: WatchKind,
+    /// Debug register used (0-3)
+    pub slot: usize,
+    pub entity_type: Type,
+    /// Contents of the location when we last looked
+    pub old_value: Vec<u8>,
+    pub hit_count: usize,
+    pub enabled: bool,
+    /// Set for watchpoints on local variables, which are deleted once their frame returns
+    pub scope: Option<WatchScope>,
+}
+
+/// The frame a watchpoint on local variables belongs to
+#[derive(Debug, Clone, Copy)]
+pub struct WatchScope {
+    /// Thread whose stack the frame is on
+    pub tid: Pid,
+    /// Canonical frame address of the frame; once %rsp is at or above it, the frame has returned
+    pub cfa: usize,
+    /// Where the frame returns to. A breakpoint there catches the return.
+    pub return_addr: usize,
+}
+
+impl Watchpoint {
+    /// The name gdb uses for this kind of watchpoint
+    pub fn description(&self) -> &'static str {
+        match self.kind {
+            WatchKind::Write => "Hardware watchpoint",
+            WatchKind::Read => "Hardware read watchpoint",
+            WatchKind::Access => "Hardware access (read/write) watchpoint",
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Reading ELF core files, for post-mortem debugging with `deet <binary> <corefile>`, and writing
+//! them from a live process for `gcore`.
+//!
+//! A core file has a PT_NOTE segment describing the process (NT_PRPSINFO), the signal that killed
+//! it (NT_SIGINFO) and each thread's registers (one NT_PRSTATUS per thread, the crashing thread
+//! first), followed by PT_LOAD segments holding the contents of memory. The kernel leaves out
+//! memory that can be read back from the executable (like its code), so reads fall back to the
+//! executable's own PT_LOAD segments.
+
+use crate::solib::parse_auxv;
+use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use object::elf::{
+    FileHeader64, ELFCLASS64, ELFDATA2LSB, ELFMAG, EM_X86_64, ET_CORE, ET_DYN, EV_CURRENT, NT_AUXV, NT_FPREGSET, NT_PRPSINFO, NT_PRSTATUS, NT_SIGINFO, PF_R, PF_W,
+    PF_X, PT_LOAD, PT_NOTE,
+};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::Endianness;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Offset of pr_pid in `struct elf_prstatus` on x86-64
+const PRSTATUS_PID: usize = 32;
+/// Offset of pr_reg (a `user_regs_struct`) in `struct elf_prstatus`
+const PRSTATUS_REGS: usize = 112;
+/// Offset of pr_psargs (the command line) in `struct elf_prpsinfo`
+const PRPSINFO_ARGS: usize = 56;
+const PRPSINFO_ARGS_LEN: usize = 80;
+/// Offset of si_addr in `siginfo_t`
+const SIGINFO_ADDR: usize = 16;
+/// Offset of pr_fpvalid in `struct elf_prstatus`, and the size of the whole struct
+const PRSTATUS_FPVALID: usize = 328;
+const PRSTATUS_SIZE: usize = 336;
+/// Offsets of pr_uid, pr_pid and pr_fname in `struct elf_prpsinfo`, and the size of the whole
+/// struct
+const PRPSINFO_UID: usize = 16;
+const PRPSINFO_PID: usize = 24;
+const PRPSINFO_FNAME: usize = 40;
+const PRPSINFO_SIZE: usize = 136;
+/// Size of the ELF header and of a program header
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PAGE_SIZE: usize = 4096;
+/// How much of a mapping `write` reads from the process at a time
+const COPY_CHUNK_SIZE: usize = 1 << 20;
+
+/// A range of the dead process's memory
+struct Segment {
+    addr: usize,
+    /// Size in memory; anything past the end of `data` reads as zeros
+    size: usize,
+    data: Vec<u8>,
+}
+
+pub struct CoreDump {
+    pid: Pid,
+    /// Registers of each thread, the thread that received the signal first
+    threads: Vec<(Pid, libc::user_regs_struct)>,
+    signal: Option<Signal>,
+    fault_address: Option<usize>,
+    command: Option<String>,
+    /// The process's auxiliary vector (NT_AUXV)
+    auxv: Vec<u8>,
+    /// Memory from the core, then from the executable and shared libraries. Earlier segments win.
+    segments: Vec<Segment>,
+}
+
+impl CoreDump {
+    /// Loads `core_path`, which was dumped by a process running `exe_path`
+    pub fn load(core_path: &str, exe_path: &str) -> Result<CoreDump, String> {
+        let data = fs::read(core_path).map_err(|err| format!("{}: {}", core_path, err))?;
+        let header = FileHeader64::<Endianness>::parse(&*data)
+            .map_err(|_| format!("\"{}\" is not a core dump: file format not recognized", core_path))?;
+        let endian = header.endian().map_err(|err| err.to_string())?;
+        if header.e_type(endian) != ET_CORE {
+            return Err(format!("\"{}\" is not a core dump", core_path));
+        }
+        let mut core = CoreDump {
+            pid: Pid::from_raw(0),
+            threads: Vec::new(),
+            signal: None,
+            fault_address: None,
+            command: None,
+            auxv: Vec::new(),
+            segments: Vec::new(),
+        };
+        let program_headers = header
+            .program_headers(endian, &*data)
+            .map_err(|err| err.to_string())?;
+        for segment in program_headers {
+            match segment.p_type(endian) {
+                PT_LOAD => {
+                    let bytes = segment.data(endian, &*data).unwrap_or_default();
+                    core.segments.push(Segment {
+                        addr: segment.p_vaddr(endian) as usize,
+                        size: segment.p_memsz(endian) as usize,
+                        data: bytes.to_vec(),
+                    });
+                }
+                PT_NOTE => {
+                    let mut notes = match segment.notes(endian, &*data) {
+                        Ok(Some(notes)) => notes,
+                        _ => continue,
+                    };
+                    while let Ok(Some(note)) = notes.next() {
+                        if note.name() == b"CORE" {
+                            core.read_note(note.n_type(endian), note.desc());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if core.threads.is_empty() {
+            return Err(format!("\"{}\" has no register information", core_path));
+        }
+        core.pid = core.threads[0].0;
+        // Only the segments the kernel actually dumped; the rest comes from the executable
+        core.segments.retain(|segment| !segment.data.is_empty());
+        core.load_executable(exe_path);
+        Ok(core)
+    }
+
+    fn read_note(&mut self, note_type: u32, desc: &[u8]) {
+        match note_type {
+            NT_PRSTATUS if desc.len() >= PRSTATUS_REGS + size_of_regs() => {
+                let pid = i32::from_le_bytes(desc[PRSTATUS_PID..PRSTATUS_PID + 4].try_into().unwrap());
+                let words: Vec<u64> = desc[PRSTATUS_REGS..PRSTATUS_REGS + size_of_regs()]
+                    .chunks_exact(8)
+                    .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                    .collect();
+                self.threads.push((Pid::from_raw(pid), regs_from_words(&words)));
+            }
+            NT_PRPSINFO if desc.len() >= PRPSINFO_ARGS + PRPSINFO_ARGS_LEN => {
+                let args = &desc[PRPSINFO_ARGS..PRPSINFO_ARGS + PRPSINFO_ARGS_LEN];
+                let end = args.iter().position(|byte| *byte == 0).unwrap_or(args.len());
+                self.command = Some(String::from_utf8_lossy(&args[..end]).trim_end().to_string());
+            }
+            NT_AUXV => self.auxv = desc.to_vec(),
+            NT_SIGINFO if desc.len() >= SIGINFO_ADDR + 8 => {
+                let signo = i32::from_le_bytes(desc[0..4].try_into().unwrap());
+                self.signal = Signal::try_from(signo).ok();
+                if matches!(self.signal, Some(Signal::SIGSEGV | Signal::SIGBUS)) {
+                    let addr = &desc[SIGINFO_ADDR..SIGINFO_ADDR + 8];
+                    self.fault_address = Some(u64::from_le_bytes(addr.try_into().unwrap()) as usize);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the executable's loadable segments, for memory the kernel didn't include in the core. A
+    /// PIE executable is placed where the entry point in the auxiliary vector says it was.
+    fn load_executable(&mut self, exe_path: &str) {
+        let entry = parse_auxv(&self.auxv).get(&libc::AT_ENTRY).copied();
+        self.map_file(exe_path, |header, endian| match entry {
+            Some(entry) if header.e_type(endian) == ET_DYN => {
+                (entry as usize).wrapping_sub(header.e_entry(endian) as usize)
+            }
+            _ => 0,
+        });
+    }
+
+    /// Adds the loadable segments of a shared library that was mapped `bias` bytes past its file
+    /// addresses. The kernel leaves its code out of the core.
+    pub fn map_library(&mut self, path: &str, bias: usize) {
+        self.map_file(path, |_, _| bias);
+    }
+
+    /// Adds the PT_LOAD segments of an ELF file, moved by the bias `bias` computes from its header
+    fn map_file(&mut self, path: &str, bias: impl Fn(&FileHeader64<Endianness>, Endianness) -> usize) {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        let header = match FileHeader64::<Endianness>::parse(&*data) {
+            Ok(header) => header,
+            Err(_) => return,
+        };
+        let endian = match header.endian() {
+            Ok(endian) => endian,
+            Err(_) => return,
+        };
+        let bias = bias(header, endian);
+        if let Ok(program_headers) = header.program_headers(endian, &*data) {
+            for segment in program_headers {
+                if segment.p_type(endian) == PT_LOAD {
+                    self.segments.push(Segment {
+                        addr: (segment.p_vaddr(endian) as usize).wrapping_add(bias),
+                        size: segment.p_memsz(endian) as usize,
+                        data: segment.data(endian, &*data).unwrap_or_default().to_vec(),
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Thread ids, the thread that received the signal first
+    pub fn tids(&self) -> Vec<Pid> {
+        self.threads.iter().map(|(tid, _)| *tid).collect()
+    }
+
+    pub fn registers(&self, tid: Pid) -> Option<libc::user_regs_struct> {
+        self.threads
+            .iter()
+            .find(|(thread, _)| *thread == tid)
+            .map(|(_, regs)| *regs)
+    }
+
+    /// The signal that terminated the process
+    pub fn signal(&self) -> Option<Signal> {
+        self.signal
+    }
+
+    pub fn fault_address(&self) -> Option<usize> {
+        self.fault_address
+    }
+
+    /// The raw auxiliary vector of the process
+    pub fn auxv(&self) -> &[u8] {
+        &self.auxv
+    }
+
+    /// The command line of the process, as far as the kernel recorded it
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    /// Reads `len` bytes of the process's memory. Returns None if any of them weren't dumped.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let cur = addr + bytes.len();
+            let segment = self
+                .segments
+                .iter()
+                .find(|segment| cur >= segment.addr && cur < segment.addr + segment.size)?;
+            let end = (segment.addr + segment.size).min(addr + len);
+            for byte_addr in cur..end {
+                bytes.push(*segment.data.get(byte_addr - segment.addr).unwrap_or(&0));
+            }
+        }
+        Some(bytes)
+    }
+}
+
+fn size_of_regs() -> usize {
+    std::mem::size_of::<libc::user_regs_struct>()
+}
+
+/// Builds a `user_regs_struct` from its fields in declaration order, as found in pr_reg
+fn regs_from_words(words: &[u64]) -> libc::user_regs_struct {
+    libc::user_regs_struct {
+        r15: words[0],
+        r14: words[1],
+        r13: words[2],
+        r12: words[3],
+        rbp: words[4],
+        rbx: words[5],
+        r11: words[6],
+        r10: words[7],
+        r9: words[8],
+        r8: words[9],
+        rax: words[10],
+        rcx: words[11],
+        rdx: words[12],
+        rsi: words[13],
+        rdi: words[14],
+        orig_rax: words[15],
+        rip: words[16],
+        cs: words[17],
+        eflags: words[18],
+        rsp: words[19],
+        ss: words[20],
+        fs_base: words[21],
+        gs_base: words[22],
+        ds: words[23],
+        es: words[24],
+        fs: words[25],
+        gs: words[26],
+    }
+}
+
+/// A line of /proc/pid/maps
+struct Mapping {
+    start: usize,
+    end: usize,
+    /// PF_R/PF_W/PF_X
+    flags: u32,
+}
+
+fn read_mappings(pid: Pid) -> io::Result<Vec<Mapping>> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    let mut mappings = Vec::new();
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let (range, perms) = match (fields.next(), fields.next()) {
+            (Some(range), Some(perms)) => (range, perms),
+            _ => continue,
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => continue,
+        };
+        let (start, end) = match (usize::from_str_radix(start, 16), usize::from_str_radix(end, 16)) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => continue,
+        };
+        let perms = perms.as_bytes();
+        let mut flags = 0;
+        if perms.first() == Some(&b'r') {
+            flags |= PF_R;
+        }
+        if perms.get(1) == Some(&b'w') {
+            flags |= PF_W;
+        }
+        if perms.get(2) == Some(&b'x') {
+            flags |= PF_X;
+        }
+        mappings.push(Mapping { start, end, flags });
+    }
+    Ok(mappings)
+}
+
+/// Appends an ELF note with the name "CORE"
+fn push_note(notes: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    notes.extend_from_slice(&5u32.to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&note_type.to_le_bytes());
+    notes.extend_from_slice(b"CORE\0\0\0\0");
+    notes.extend_from_slice(desc);
+    notes.resize(notes.len().next_multiple_of(4), 0);
+}
+
+fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn prstatus(tid: Pid, regs: &libc::user_regs_struct, fpvalid: bool) -> Vec<u8> {
+    let mut desc = vec![0u8; PRSTATUS_SIZE];
+    put(&mut desc, PRSTATUS_PID, &tid.as_raw().to_le_bytes());
+    let words = [
+        regs.r15, regs.r14, regs.r13, regs.r12, regs.rbp, regs.rbx, regs.r11, regs.r10, regs.r9,
+        regs.r8, regs.rax, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.orig_rax, regs.rip,
+        regs.cs, regs.eflags, regs.rsp, regs.ss, regs.fs_base, regs.gs_base, regs.ds, regs.es,
+        regs.fs, regs.gs,
+    ];
+    for (i, word) in words.iter().enumerate() {
+        put(&mut desc, PRSTATUS_REGS + i * 8, &word.to_le_bytes());
+    }
+    put(&mut desc, PRSTATUS_FPVALID, &(fpvalid as i32).to_le_bytes());
+    desc
+}
+
+fn prpsinfo(pid: Pid) -> Vec<u8> {
+    let mut desc = vec![0u8; PRPSINFO_SIZE];
+    desc[1] = b'R';
+    let uid = unsafe { libc::getuid() };
+    put(&mut desc, PRPSINFO_UID, &uid.to_le_bytes());
+    put(&mut desc, PRPSINFO_PID, &pid.as_raw().to_le_bytes());
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    let comm = comm.trim_end().as_bytes();
+    put(&mut desc, PRPSINFO_FNAME, &comm[..comm.len().min(15)]);
+    // The arguments are NUL-separated in /proc; the core has them separated by spaces
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let args: Vec<u8> = cmdline
+        .iter()
+        .map(|byte| if *byte == 0 { b' ' } else { *byte })
+        .collect();
+    let args = String::from_utf8_lossy(&args).trim_end().to_string();
+    let args = args.as_bytes();
+    put(&mut desc, PRPSINFO_ARGS, &args[..args.len().min(PRPSINFO_ARGS_LEN - 1)]);
+    desc
+}
+
+/// The floating point registers of a thread (`struct user_fpregs_struct`), if they can be read
+fn fpregs(tid: Pid) -> Option<Vec<u8>> {
+    let mut regs = std::mem::MaybeUninit::<libc::user_fpregs_struct>::uninit();
+    let result = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETFPREGS,
+            tid.as_raw(),
+            std::ptr::null_mut::<libc::c_void>(),
+            regs.as_mut_ptr(),
+        )
+    };
+    if result < 0 {
+        return None;
+    }
+    let size = std::mem::size_of::<libc::user_fpregs_struct>();
+    let bytes = unsafe { std::slice::from_raw_parts(regs.as_ptr() as *const u8, size) };
+    Some(bytes.to_vec())
+}
+
+/// The `siginfo_t` of the signal a thread is stopped with, if it can be read
+fn siginfo(tid: Pid) -> Option<Vec<u8>> {
+    let siginfo = ptrace::getsiginfo(tid).ok()?;
+    let size = std::mem::size_of::<libc::siginfo_t>();
+    let bytes = unsafe { std::slice::from_raw_parts(&siginfo as *const _ as *const u8, size) };
+    Some(bytes.to_vec())
+}
+
+/// Writes a core file of the stopped process `pid`, which gdb (and `deet <binary> <core>`) can
+/// load. `threads` holds the registers of each thread, the current thread first. `breakpoints`
+/// maps the addresses of inserted breakpoints to their original bytes, so that the core shows
+/// the program's own code.
+pub fn write(
+    path: &str,
+    pid: Pid,
+    threads: &[(Pid, libc::user_regs_struct)],
+    breakpoints: &HashMap<usize, u8>,
+) -> io::Result<()> {
+    let mut notes = Vec::new();
+    for (i, (tid, regs)) in threads.iter().enumerate() {
+        let fpregs = fpregs(*tid);
+        push_note(&mut notes, NT_PRSTATUS, &prstatus(*tid, regs, fpregs.is_some()));
+        if i == 0 {
+            push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid));
+            if let Some(siginfo) = siginfo(*tid) {
+                push_note(&mut notes, NT_SIGINFO, &siginfo);
+            }
+            if let Ok(auxv) = fs::read(format!("/proc/{}/auxv", pid)) {
+                push_note(&mut notes, NT_AUXV, &auxv);
+            }
+        }
+        if let Some(fpregs) = fpregs {
+            push_note(&mut notes, NT_FPREGSET, &fpregs);
+        }
+    }
+
+    let mappings = read_mappings(pid)?;
+    let phnum = 1 + mappings.len();
+    let notes_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let mut out = Vec::new();
+
+    // ELF header
+    let mut ident = [0u8; 16];
+    ident[..4].copy_from_slice(&ELFMAG);
+    ident[4] = ELFCLASS64;
+    ident[5] = ELFDATA2LSB;
+    ident[6] = EV_CURRENT;
+    out.extend_from_slice(&ident);
+    out.extend_from_slice(&ET_CORE.to_le_bytes());
+    out.extend_from_slice(&EM_X86_64.to_le_bytes());
+    out.extend_from_slice(&(EV_CURRENT as u32).to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    out.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(phnum as u16).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    // The program headers are filled in once the mappings have been copied and it's known how
+    // much of each could be read
+    out.resize(notes_offset, 0);
+    out.extend_from_slice(&notes);
+    out.resize(out.len().next_multiple_of(PAGE_SIZE), 0);
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    file.write_all(&out)?;
+
+    // Copy each mapping a chunk at a time. Mappings that can't be read (like [vvar]) are recorded
+    // without contents, and one that stops being readable part way through keeps what was read.
+    let mut mem = fs::File::open(format!("/proc/{}/mem", pid))?;
+    let mut chunk = vec![0u8; COPY_CHUNK_SIZE];
+    let mut offset = out.len();
+    let mut phdrs = Vec::new();
+    let mut push_phdr = |p_type: u32, flags: u32, offset: usize, vaddr: usize, filesz: usize, memsz: usize, align: usize| {
+        phdrs.extend_from_slice(&p_type.to_le_bytes());
+        phdrs.extend_from_slice(&flags.to_le_bytes());
+        phdrs.extend_from_slice(&(offset as u64).to_le_bytes());
+        phdrs.extend_from_slice(&(vaddr as u64).to_le_bytes());
+        phdrs.extend_from_slice(&0u64.to_le_bytes()); // p_paddr
+        phdrs.extend_from_slice(&(filesz as u64).to_le_bytes());
+        phdrs.extend_from_slice(&(memsz as u64).to_le_bytes());
+        phdrs.extend_from_slice(&(align as u64).to_le_bytes());
+    };
+    push_phdr(PT_NOTE, 0, notes_offset, 0, notes.len(), 0, 4);
+    for mapping in &mappings {
+        let mut copied = 0;
+        if mapping.flags & PF_R != 0 && mem.seek(SeekFrom::Start(mapping.start as u64)).is_ok() {
+            while mapping.start + copied < mapping.end {
+                let addr = mapping.start + copied;
+                let data = &mut chunk[..COPY_CHUNK_SIZE.min(mapping.end - addr)];
+                if mem.read_exact(data).is_err() {
+                    break;
+                }
+                for (bp_addr, byte) in breakpoints {
+                    if (addr..addr + data.len()).contains(bp_addr) {
+                        data[bp_addr - addr] = *byte;
+                    }
+                }
+                file.write_all(data)?;
+                copied += data.len();
+            }
+        }
+        let size = mapping.end - mapping.start;
+        push_phdr(PT_LOAD, mapping.flags, offset, mapping.start, copied, size, PAGE_SIZE);
+        offset += copied;
+    }
+
+    file.seek(SeekFrom::Start(EHDR_SIZE as u64))?;
+    file.write_all(&phdrs)?;
+    file.flush()
+}
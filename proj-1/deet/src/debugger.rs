@@ -1,12 +1,24 @@
+use crate::breakpoint::{Breakpoint, Condition, WatchKind, WatchScope, Watchpoint};
+use crate::coredump::CoreDump;
 use crate::debugger_command::DebuggerCommand;
-use crate::inferior::{Inferior, Status};
-// use nix::sys::ptrace;
+use crate::disassembler;
+use crate::expression::{self, Evaluator, Expr};
+use crate::inferior::{FollowForkMode, Frame, Inferior, Status};
+use crate::interpreter::JsonInterpreter;
+use crate::signals::{self, SignalTable};
+use crate::solib;
+use crate::value_format::ValueFormatter;
 use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use rustyline::error::ReadlineError;
 use rustyline::history::FileHistory;
 use rustyline::Editor;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use crate::dwarf_data::{
+    DwarfData, Error as DwarfError, Line, Location, Type, Variable,
+};
+use std::collections::VecDeque;
+use std::fs;
 
 pub struct Debugger {
     target: String,
@@ -14,7 +26,30 @@ pub struct Debugger {
     readline: Editor<(), FileHistory>,
     inferior: Option<Inferior>,
     debug_data: DwarfData,
-    breakpoints: HashMap<usize, u8>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_breakpoint_id: usize,
+    /// Expressions shown whenever the inferior stops, added by `display`
+    displays: Vec<(usize, String, Expr)>,
+    next_display_id: usize,
+    /// Level of the frame that print/info locals operate on; 0 is the innermost frame. Reset
+    /// whenever the inferior stops.
+    selected_frame: usize,
+    /// Source file and last line shown by `list`, so that a bare `list` continues from there
+    last_listed: Option<(String, usize)>,
+    signals: SignalTable,
+    follow_fork_mode: FollowForkMode,
+    /// Lines from command files and breakpoint command lists, run before reading from the user
+    pending_lines: VecDeque<String>,
+    /// Quit once there are no more pending lines instead of prompting
+    batch: bool,
+    /// Set in `--interpreter=json` mode, where commands come from stdin without a prompt and their
+    /// results go out as JSON objects
+    interpreter: Option<JsonInterpreter>,
+    /// Address of the dynamic linker's `_dl_debug_state`, which it calls whenever it changes its
+    /// list of shared libraries, and of its `struct r_debug` holding that list
+    shlib_event: Option<usize>,
+    r_debug: Option<usize>,
 }
 
 impl Debugger {
@@ -32,7 +67,6 @@ impl Debugger {
                 std::process::exit(1);
             }
         };
-        debug_data.print();
 
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
         let mut readline = Editor::<(), FileHistory>::new().expect("Create Editor fail");
@@ -45,22 +79,72 @@ impl Debugger {
             readline,
             inferior: None,
             debug_data,
-            breakpoints: HashMap::new(),
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_breakpoint_id: 0,
+            displays: Vec::new(),
+            next_display_id: 1,
+            selected_frame: 0,
+            last_listed: None,
+            signals: SignalTable::default(),
+            follow_fork_mode: FollowForkMode::Parent,
+            pending_lines: VecDeque::new(),
+            batch: false,
+            interpreter: None,
+            shlib_event: None,
+            r_debug: None,
         }
     }
 
+    /// Dumps the files, variables, functions and lines loaded from the target's DWARF, as shown at
+    /// startup outside of JSON mode
+    pub fn print_debug_info(&self) {
+        self.debug_data.print();
+    }
+
+    pub fn set_interpreter(&mut self, interpreter: JsonInterpreter) {
+        self.interpreter = Some(interpreter);
+    }
+
+    pub fn set_batch(&mut self, batch: bool) {
+        self.batch = batch;
+    }
+
+    /// Queues the commands in `path` to run next, ahead of anything already queued (so that a
+    /// file can `source` another one). Blank lines and `#` comments are skipped.
+    pub fn source(&mut self, path: &str) -> Result<(), ()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("{}: {}.", path, err);
+                return Err(());
+            }
+        };
+        let lines = contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for line in lines.rev() {
+            self.pending_lines.push_front(line.to_string());
+        }
+        Ok(())
+    }
+
     pub fn run(&mut self) {
+        if let Some(interpreter) = self.interpreter.as_mut() {
+            interpreter.ready(&self.target);
+        }
         loop {
-            match self.get_next_command() {
+            let command = self.get_next_command();
+            match command {
                 DebuggerCommand::Run(args) => {
-                    if self.inferior.is_some(){
-                        // println!("There exit running process!");
-                        self.inferior.as_mut().unwrap().kill();
-                        self.inferior = None;
-                    }
-                    if let Some(inferior) = Inferior::new(&self.target, &args, self.breakpoints.clone()) {
+                    self.drop_inferior();
+                    let stdio = self.interpreter.as_ref().and_then(|interpreter| interpreter.program_stdio().ok());
+                    if let Some(inferior) = Inferior::new(&self.target, &args, &[], stdio) {
                         // Create the inferior
                         self.inferior = Some(inferior);
+                        self.program_started();
+                        self.configure_inferior();
                         // (milestone 1): make the inferior run
                         // You may use self.inferior.as_mut().unwrap() to get a mutable reference
                         // to the Inferior object
@@ -71,79 +155,1512 @@ impl Debugger {
                         eprintln!("Error starting subprocess");
                     }
                 },
+                DebuggerCommand::Malformed(message) => eprintln!("{}", message),
                 DebuggerCommand::Quit => {
-                    if self.inferior.is_some() {
-                        // println!("There exit running process!");
-                        self.inferior.as_mut().unwrap().kill();
-                        self.inferior = None;
+                    self.drop_inferior();
+                    if let Some(interpreter) = self.interpreter.as_mut() {
+                        interpreter.finish_command();
                     }
                     return;
                 },
+                DebuggerCommand::Attach(pid) => self.attach(pid),
+                DebuggerCommand::Detach => {
+                    match self.inferior.as_mut() {
+                        None => eprintln!("No existing inferior is running!"),
+                        Some(inferior) if inferior.core().is_some() => {
+                            println!("No core file now.");
+                            self.inferior = None;
+                        }
+                        Some(inferior) => {
+                            if inferior.detach().is_err() {
+                                eprintln!("Detach failed!");
+                            }
+                            self.inferior = None;
+                        }
+                    }
+                },
                 DebuggerCommand::Continue => {
                     if self.inferior.is_none() {
                         eprintln!("No existing inferior is running!");
-                    } else {
-                        if self.inferior_continue_execute().is_err() {
-                            eprintln!("Continue Execute failed!");
-                        }
+                    } else if self.inferior.as_ref().unwrap().core().is_some() {
+                        eprintln!("The program is not being run.");
+                    } else if self.inferior_continue_execute().is_err() {
+                        eprintln!("Continue Execute failed!");
                     }
                 },
                 DebuggerCommand::Backtrace => {
-                    if self.inferior.is_none() {
-                        eprintln!("No existing inferior is running!");
-                    } else {
-                        if self.inferior.as_ref().unwrap().print_backtrace(&self.debug_data).is_err() {
-                            eprintln!("Backtrace failed!");
+                    match &self.inferior {
+                        None => eprintln!("No existing inferior is running!"),
+                        Some(inferior) => {
+                            if inferior.print_backtrace(&self.debug_data).is_err() {
+                                eprintln!("Backtrace failed!");
+                            }
                         }
                     }
                 },
-                DebuggerCommand::Breakpoint(breakpoint) => {
-                    let mut breakpoint_address: usize = 0;
-                    if breakpoint.starts_with("*") {    // raw address
-                        if let Some(bp_addr) = Debugger::parse_address(&breakpoint[1..]) {
-                            breakpoint_address = bp_addr;
+                DebuggerCommand::Step
+                | DebuggerCommand::Next
+                | DebuggerCommand::StepInstruction
+                | DebuggerCommand::Finish => {
+                    let inferior = match self.inferior.as_mut() {
+                        Some(inferior) if inferior.core().is_some() => {
+                            eprintln!("The program is not being run.");
+                            continue;
                         }
-                    } else if let Ok(line_number) = breakpoint.parse::<usize>() {   // line number
-                        if let Some(addr) = self.debug_data.get_addr_for_line(None, line_number) {
-                            breakpoint_address = addr;
+                        Some(inferior) => inferior,
+                        None => {
+                            eprintln!("No existing inferior is running!");
+                            continue;
                         }
-                    } else if let Some(addr) = self.debug_data.get_addr_for_function(None, &breakpoint) {
-                        breakpoint_address = addr;
-                    } else {
-                        eprintln!("{} can't be parsed to a valid breakpoint address!", breakpoint);
-                        eprintln!("Usage: {{b | break | breakpoint}} *{{raw address | line number | function name}}");
+                    };
+                    let breakpoints = &mut self.breakpoints;
+                    let debug_data = &self.debug_data;
+                    let shlib_event = self.shlib_event;
+                    let mut libraries_changed = false;
+                    let mut triggered = None;
+                    let mut should_stop = |inferior: &Inferior, addr: usize| {
+                        // Shared libraries are caught up with once stepping is done
+                        if Some(addr) == shlib_event {
+                            libraries_changed = true;
+                            return false;
+                        }
+                        match Debugger::breakpoint_should_stop(breakpoints, debug_data, inferior, addr) {
+                            Some(ids) => {
+                                triggered = Some((addr, ids));
+                                true
+                            }
+                            None => false,
+                        }
+                    };
+                    let result = match command {
+                        DebuggerCommand::Step => inferior.step_line(debug_data, false, &mut should_stop),
+                        DebuggerCommand::Next => inferior.step_line(debug_data, true, &mut should_stop),
+                        DebuggerCommand::StepInstruction => inferior.step_instruction(),
+                        _ => inferior.finish(debug_data, &mut should_stop),
+                    };
+                    if libraries_changed && matches!(result, Ok(Status::Stopped(..))) {
+                        self.load_libraries();
+                    }
+                    if self.report_status(result, triggered).is_err() {
+                        eprintln!("Stepping failed!");
+                    }
+                },
+                DebuggerCommand::Print(expression) => {
+                    if self.current_pc().is_none() {
+                        continue;
+                    }
+                    // A lone register is shown the way `info registers` shows it
+                    if let Some(register) = expression
+                        .strip_prefix('$')
+                        .filter(|name| name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'))
+                    {
+                        match self.inferior.as_ref().unwrap().get_register(register) {
+                            Ok(Some(value)) => println!("${} = {}", register, format_register(register, value)),
+                            Ok(None) => eprintln!("Invalid register \"{}\"", register),
+                            Err(_) => eprintln!("Reading registers failed!"),
+                        }
+                        continue;
+                    }
+                    match self.evaluate(&expression) {
+                        Ok(value) => println!("{} = {}", expression, self.format_value(&value)),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                },
+                DebuggerCommand::Display(None) => self.show_displays(),
+                DebuggerCommand::Display(Some(expression)) => {
+                    match expression::parse(&expression, &self.debug_data) {
+                        Ok(expr) => {
+                            self.displays.push((self.next_display_id, expression, expr));
+                            self.next_display_id += 1;
+                            if self.inferior.is_some() {
+                                self.show_display(self.displays.last().unwrap());
+                            }
+                        }
+                        Err(err) => eprintln!("{}", err),
+                    }
+                },
+                DebuggerCommand::Undisplay(ids) => {
+                    if ids.is_empty() {
+                        self.displays.clear();
+                    }
+                    for id in ids {
+                        match self.displays.iter().position(|(display_id, _, _)| *display_id == id) {
+                            Some(index) => {
+                                self.displays.remove(index);
+                            }
+                            None => eprintln!("No display number {}.", id),
+                        }
+                    }
+                },
+                DebuggerCommand::InfoDisplay => {
+                    if self.displays.is_empty() {
+                        println!("There are no auto-display expressions now.");
+                        continue;
+                    }
+                    println!("Auto-display expressions now in effect:");
+                    println!("Num Enb Expression");
+                    for (id, expression, _) in &self.displays {
+                        println!("{}:   y  {}", id, expression);
+                    }
+                },
+                DebuggerCommand::Up(count) => {
+                    let level = self.selected_frame + count;
+                    self.select_frame(level, "Initial frame selected; you cannot go up.");
+                },
+                DebuggerCommand::Down(count) => match self.selected_frame.checked_sub(count) {
+                    Some(level) => self.select_frame(level, ""),
+                    None => eprintln!("Bottom (innermost) frame selected; you cannot go down."),
+                },
+                DebuggerCommand::Frame(level) => {
+                    let level = level.unwrap_or(self.selected_frame);
+                    self.select_frame(level, &format!("No frame at level {}.", level));
+                },
+                DebuggerCommand::List(spec) => self.list_source(spec.as_deref()),
+                DebuggerCommand::Disassemble(spec) => {
+                    if self.current_pc().is_some() {
+                        self.disassemble(spec.as_deref());
+                    }
+                },
+                DebuggerCommand::Handle(signal, keywords) => {
+                    let signal = match signals::parse_signal(&signal) {
+                        Some(signal) => signal,
+                        None => {
+                            eprintln!("Unrecognized signal \"{}\"", signal);
+                            continue;
+                        }
+                    };
+                    if let Err(keyword) = self.signals.update(signal, &keywords) {
+                        eprintln!("Unrecognized or ambiguous flag word: \"{}\".", keyword);
+                        continue;
+                    }
+                    if let Some(inferior) = self.inferior.as_mut() {
+                        inferior.set_signal_table(self.signals.clone());
+                    }
+                    let policy = self.signals.get(signal);
+                    let yes_no = |flag| if flag { "Yes" } else { "No" };
+                    println!("Signal        Stop\tPrint\tPass to program");
+                    println!(
+                        "{:<14}{}\t{}\t{}",
+                        signal.as_str(),
+                        yes_no(policy.stop),
+                        yes_no(policy.print),
+                        yes_no(policy.pass)
+                    );
+                },
+                DebuggerCommand::InfoThreads => {
+                    if self.current_pc().is_some() {
+                        self.print_threads();
+                    }
+                },
+                DebuggerCommand::Thread(number) => {
+                    let inferior = match self.inferior.as_mut() {
+                        Some(inferior) => inferior,
+                        None => {
+                            eprintln!("No existing inferior is running!");
+                            continue;
+                        }
+                    };
+                    if let Some(number) = number {
+                        if !inferior.select_thread(number) {
+                            eprintln!("Invalid thread ID: {}", number);
+                            continue;
+                        }
+                        self.selected_frame = 0;
+                        self.last_listed = None;
+                    }
+                    let tid = inferior.tid();
+                    let thread = inferior.threads().iter().find(|thread| thread.tid == tid).copied();
+                    if let Some(thread) = thread {
+                        println!("[Current thread is {} ({})]", thread.number, thread.tid);
+                    }
+                    self.select_frame(0, "No stack.");
+                },
+                DebuggerCommand::Gcore(file) => match self.inferior.as_ref() {
+                    Some(inferior) if inferior.core().is_none() => {
+                        let file = file.unwrap_or_else(|| format!("core.{}", inferior.pid()));
+                        match inferior.generate_core(&file) {
+                            Ok(()) => println!("Saved corefile {}", file),
+                            Err(err) => eprintln!("Can't create a corefile: {}", err),
+                        }
+                    }
+                    _ => eprintln!("The program is not being run."),
+                },
+                DebuggerCommand::Source(path) => {
+                    let _ = self.source(&path);
+                },
+                DebuggerCommand::Commands(id) => {
+                    let id = match id.or_else(|| self.breakpoints.last().map(|bp| bp.id)) {
+                        Some(id) => id,
+                        None => {
+                            eprintln!("No breakpoints specified.");
+                            continue;
+                        }
+                    };
+                    if !self.breakpoints.iter().any(|bp| bp.id == id) {
+                        eprintln!("No breakpoint number {}.", id);
+                        continue;
+                    }
+                    if self.pending_lines.is_empty() {
+                        println!("Type commands for breakpoint {}, one per line.", id);
+                        println!("End with a line saying just \"end\".");
+                    }
+                    let commands = self.read_command_list();
+                    if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+                        bp.commands = commands;
+                    }
+                },
+                DebuggerCommand::SetFollowForkMode(mode) => {
+                    self.follow_fork_mode = mode;
+                    if let Some(inferior) = self.inferior.as_mut() {
+                        inferior.set_follow_fork_mode(mode);
+                    }
+                },
+                DebuggerCommand::InfoRegisters(names) => {
+                    if self.current_pc().is_some() {
+                        self.print_registers(&names);
+                    }
+                },
+                DebuggerCommand::Examine(count, format, size, location) => {
+                    if self.current_pc().is_none() {
                         continue;
                     }
+                    match self.evaluate_address(&location) {
+                        Ok(addr) => self.examine_memory(addr, count, format, size),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                },
+                DebuggerCommand::Set(target, value) => {
+                    if self.current_pc().is_some() {
+                        if let Err(err) = self.set_value(&target, &value) {
+                            eprintln!("{}", err);
+                        }
+                    }
+                },
+                DebuggerCommand::InfoLocals | DebuggerCommand::InfoArgs => {
+                    if self.current_pc().is_none() {
+                        continue;
+                    }
+                    let evaluator = match self.evaluator() {
+                        Some(evaluator) => evaluator,
+                        None => continue,
+                    };
+                    let frame = match evaluator.frame() {
+                        Some(frame) => frame,
+                        None => continue,
+                    };
+                    let want_parameters = matches!(command, DebuggerCommand::InfoArgs);
+                    let vars: Vec<&Variable> = self
+                        .debug_data
+                        .get_local_variables(frame.lookup_pc(), frame.inline_depth)
+                        .into_iter()
+                        .filter(|var| var.is_parameter == want_parameters)
+                        .collect();
+                    if vars.is_empty() {
+                        println!("{}", if want_parameters { "No arguments." } else { "No locals." });
+                    }
+                    for var in vars {
+                        self.print_variable(&evaluator, var);
+                    }
+                },
+                DebuggerCommand::Breakpoint(breakpoint, condition) => {
+                    let breakpoint_address = match self.resolve_location(&breakpoint) {
+                        Some(addr) => Some(addr),
+                        // Until the program runs, its shared libraries aren't loaded, so the
+                        // location may still turn up in one of them
+                        None if !breakpoint.starts_with('*')
+                            && self.inferior.is_none()
+                            && self.debug_data.interpreter().is_some() =>
+                        {
+                            match split_file_location(&breakpoint) {
+                                Some((file, _)) if self.debug_data.get_source_path(Some(file)).is_none() => {
+                                    println!("No source file named {}.", file)
+                                }
+                                _ => println!("Function \"{}\" not defined.", breakpoint),
+                            }
+                            None
+                        }
+                        None => {
+                            match split_file_location(&breakpoint) {
+                                Some((file, _)) if self.debug_data.get_source_path(Some(file)).is_none() => {
+                                    eprintln!("No source file named {}.", file)
+                                }
+                                _ => {
+                                    eprintln!("{} can't be parsed to a valid breakpoint address!", breakpoint);
+                                    eprintln!("Usage: {{b | break | breakpoint}} {{*raw address | [file:]line number | [file:]function name}} [if <condition>]");
+                                }
+                            }
+                            continue;
+                        }
+                    };
+                    let condition = match condition.map(|text| Condition::parse(&text, &self.debug_data)) {
+                        None => None,
+                        Some(Ok(condition)) => Some(condition),
+                        Some(Err(err)) => {
+                            eprintln!("{}", err);
+                            continue;
+                        }
+                    };
 
-                    self.breakpoints.insert(breakpoint_address, 0);
-                    if self.inferior.is_none() {
-                        println!("Set breakpoint {} at {:#x}", self.breakpoints.len() - 1, breakpoint_address);
+                    let id = self.next_breakpoint_id;
+                    // Breakpoints set while looking at a core only take effect once the program runs
+                    let installed = match (self.inferior.as_mut(), breakpoint_address) {
+                        (Some(inferior), Some(addr)) if inferior.core().is_none() => inferior.insert_breakpoint(addr),
+                        _ => Ok(()),
+                    };
+                    if installed.is_err() {
+                        eprintln!("Breakpoint Install failed!");
                     } else {
-                        if self.inferior.as_mut().unwrap().insert_breakpoint(breakpoint_address).is_err() {
-                            eprintln!("Breakpoint Install failed!");
-                        } else {
-                            println!("Set breakpoint {} at {:#x}", self.breakpoints.len() - 1, breakpoint_address);
+                        self.next_breakpoint_id += 1;
+                        let mut bp = Breakpoint::new(
+                            id,
+                            breakpoint_address.unwrap_or(0),
+                            &breakpoint,
+                            breakpoint_address.and_then(|addr| self.debug_data.get_function_from_addr(addr)),
+                            breakpoint_address.and_then(|addr| self.debug_data.get_line_from_addr(addr)),
+                        );
+                        bp.condition = condition;
+                        bp.pending = breakpoint_address.is_none();
+                        match &bp.line {
+                            _ if bp.pending => println!("Breakpoint {} ({}) pending.", id, breakpoint),
+                            Some(line) => println!(
+                                "Set breakpoint {} at {:#x}: file {}, line {}.",
+                                id, bp.addr, line.file, line.number
+                            ),
+                            None => println!("Set breakpoint {} at {:#x}", id, bp.addr),
                         }
+                        self.breakpoints.push(bp);
+                    }
+                },
+                DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+                DebuggerCommand::InfoSharedLibrary => self.print_shared_libraries(),
+                DebuggerCommand::Delete(ids) => {
+                    for id in self.resolve_breakpoint_ids(&ids) {
+                        self.set_breakpoint_enabled(id, false);
+                        self.breakpoints.retain(|bp| bp.id != id);
+                        self.delete_watchpoints(|wp| wp.id == id);
+                    }
+                },
+                DebuggerCommand::Disable(ids) => {
+                    for id in self.resolve_breakpoint_ids(&ids) {
+                        self.set_breakpoint_enabled(id, false);
+                    }
+                },
+                DebuggerCommand::Enable(ids) => {
+                    for id in self.resolve_breakpoint_ids(&ids) {
+                        self.set_breakpoint_enabled(id, true);
+                    }
+                },
+                DebuggerCommand::Watch(expression, kind) => self.add_watchpoint(&expression, kind),
+                DebuggerCommand::Ignore(id, count) => {
+                    match self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+                        Some(bp) => {
+                            bp.ignore_count = count;
+                            println!("Will ignore next {} crossings of breakpoint {}.", count, id);
+                        }
+                        None => eprintln!("No breakpoint number {}.", id),
                     }
                 },
             }
         }
     }
 
+    /// Turns a breakpoint location (`*addr`, a line number or a function name) into an address
+    fn resolve_location(&self, location: &str) -> Option<usize> {
+        if let Some(raw_addr) = location.strip_prefix('*') {    // raw address
+            return Debugger::parse_address(raw_addr);
+        }
+        let (file, location) = match split_file_location(location) {
+            Some((file, location)) => (Some(file), location),
+            None => (None, location),
+        };
+        if let Ok(line_number) = location.parse::<usize>() {   // line number
+            self.debug_data.get_addr_for_line(file, line_number)
+        } else {
+            self.debug_data.get_addr_for_function(file, location)
+        }
+    }
+
+    /// Decides whether the inferior should stop at the breakpoint address `addr`. Every enabled
+    /// breakpoint there whose condition holds counts a hit; we stop unless all of them are still
+    /// being ignored. Returns the ids of the breakpoints that stopped the inferior, or None if it
+    /// should carry on.
+    fn breakpoint_should_stop(
+        breakpoints: &mut [Breakpoint],
+        debug_data: &DwarfData,
+        inferior: &Inferior,
+        addr: usize,
+    ) -> Option<Vec<usize>> {
+        let mut any_enabled = false;
+        let mut triggered = Vec::new();
+        for bp in breakpoints.iter_mut().filter(|bp| bp.enabled && !bp.pending && bp.addr == addr) {
+            any_enabled = true;
+            let condition_holds = match &bp.condition {
+                None => true,
+                Some(condition) => {
+                    match Debugger::condition_holds(debug_data, inferior, condition) {
+                        Ok(holds) => holds,
+                        Err(err) => {
+                            eprintln!("Error in testing condition for breakpoint {}: {}", bp.id, err);
+                            true
+                        }
+                    }
+                }
+            };
+            if !condition_holds {
+                continue;
+            }
+            bp.hit_count += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+            } else {
+                triggered.push(bp.id);
+            }
+        }
+        // Traps that aren't user breakpoints (e.g. our own temporary ones) always stop
+        (!triggered.is_empty() || !any_enabled).then_some(triggered)
+    }
+
+    /// Evaluates a breakpoint condition in the innermost frame
+    fn condition_holds(
+        debug_data: &DwarfData,
+        inferior: &Inferior,
+        condition: &Condition,
+    ) -> Result<bool, String> {
+        let frame = inferior.current_frame(debug_data).map_err(|err| err.to_string())?;
+        let evaluator = Evaluator::new(debug_data, inferior, Some(frame));
+        evaluator.is_true(&evaluator.evaluate(&condition.expr)?)
+    }
+
+    /// Gets rid of the current inferior, if any: processes we started are killed, processes we
+    /// attached to are detached from and left running
+    fn drop_inferior(&mut self) {
+        // Watchpoints on locals die with the frames they were in
+        self.delete_watchpoints(|wp| wp.scope.is_some());
+        if let Some(inferior) = self.inferior.as_mut() {
+            if inferior.is_attached() {
+                if inferior.detach().is_err() {
+                    eprintln!("Detach failed!");
+                }
+            } else {
+                inferior.kill();
+            }
+        }
+        self.inferior = None;
+    }
+
+    /// Starts debugging the already-running process `pid`. If it is running a different
+    /// executable than the one we were started with, its debugging symbols are loaded instead.
+    pub fn attach(&mut self, pid: i32) {
+        self.drop_inferior();
+        let exe = match fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(exe) => exe,
+            Err(err) => {
+                eprintln!("Could not attach to process {}: {}", pid, err);
+                return;
+            }
+        };
+        if fs::canonicalize(&self.target).ok().as_ref() != Some(&exe)
+            && !self.load_symbols(&exe.to_string_lossy())
+        {
+            return;
+        }
+        match Inferior::attach(Pid::from_raw(pid), &[]) {
+            Some(inferior) => {
+                println!("Attaching to process {}", pid);
+                self.inferior = Some(inferior);
+                self.selected_frame = 0;
+                self.last_listed = None;
+                self.program_started();
+                self.configure_inferior();
+                if let Some(rip) = self.current_pc() {
+                    self.print_stopped_location(Signal::SIGSTOP, rip);
+                }
+            }
+            None => eprintln!("Error attaching to process {}", pid),
+        }
+    }
+
+    /// Loads a core file dumped by the target, to inspect the state it died in
+    pub fn load_core(&mut self, path: &str) {
+        let core = match CoreDump::load(path, &self.target) {
+            Ok(core) => core,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        };
+        self.drop_inferior();
+        if let Some(command) = core.command() {
+            println!("Core was generated by `{}'.", command);
+        }
+        if let Some(signal) = core.signal() {
+            println!("Program terminated with signal {}.", signal.as_str());
+        }
+        if let Some(addr) = core.fault_address() {
+            println!("Fault address: {:#x}", addr);
+        }
+        self.inferior = Some(Inferior::from_core(core));
+        self.selected_frame = 0;
+        self.last_listed = None;
+        self.program_started();
+        self.select_frame(0, "No stack.");
+    }
+
+    /// Switches to debugging the program at `exe`. Returns false if its symbols can't be loaded.
+    fn load_symbols(&mut self, exe: &str) -> bool {
+        match DwarfData::from_file(exe) {
+            Ok(debug_data) => {
+                println!("Reading symbols from {}", exe);
+                self.debug_data = debug_data;
+                self.target = exe.to_string();
+                true
+            }
+            Err(err) => {
+                eprintln!("Could not load debugging symbols from {}: {:?}", exe, err);
+                false
+            }
+        }
+    }
+
+    /// The inferior exec'd `exe`: load its symbols, move breakpoints to where their locations are
+    /// in the new program, and keep going.
+    fn follow_exec(&mut self, exe: &str) -> Result<(), ()> {
+        println!("process {} is executing new program: {}", self.inferior.as_ref().unwrap().pid(), exe);
+        if self.load_symbols(exe) {
+            let resolved: Vec<usize> =
+                self.breakpoints.iter().filter(|bp| !bp.pending).map(|bp| bp.id).collect();
+            self.program_started();
+            for bp in self.breakpoints.iter().filter(|bp| bp.pending && resolved.contains(&bp.id)) {
+                println!("Breakpoint {} pending: \"{}\" not found in new program", bp.id, bp.spec);
+            }
+        } else {
+            let inferior = self.inferior.as_mut().unwrap();
+            for addr in self.breakpoints.iter().filter(|bp| bp.enabled && !bp.pending).map(|bp| bp.addr) {
+                if inferior.insert_breakpoint(addr).is_err() {
+                    eprintln!("Breakpoint Install failed!");
+                }
+            }
+        }
+        self.inferior_continue_execute()
+    }
+
+    /// Sets up a process that was just started, attached to, exec'd or loaded from a core:
+    /// relocates a PIE executable to where it was loaded, finds the shared libraries loaded so
+    /// far, and puts breakpoints in place.
+    fn program_started(&mut self) {
+        let auxv = self.inferior.as_ref().unwrap().auxv();
+        self.debug_data.retain_libraries(|_| false);
+        self.shlib_event = None;
+        self.r_debug = None;
+        if let Some(entry) = auxv.get(&libc::AT_ENTRY) {
+            let in_executable: Vec<bool> =
+                self.watchpoints.iter().map(|wp| self.debug_data.in_executable(wp.addr)).collect();
+            let delta = self.debug_data.relocate_executable(*entry as usize);
+            for (wp, moved) in self.watchpoints.iter_mut().zip(in_executable) {
+                if moved {
+                    wp.addr = wp.addr.wrapping_add(delta);
+                }
+            }
+        }
+        let interpreter = self.debug_data.interpreter().map(str::to_string);
+        let base = auxv.get(&libc::AT_BASE).copied().unwrap_or(0) as usize;
+        if let (Some(path), true) = (interpreter, base != 0) {
+            match self.debug_data.load_library(&path, base) {
+                Ok(_) => {
+                    self.inferior.as_mut().unwrap().library_loaded(&path, base);
+                    self.shlib_event = self.debug_data.get_symbol_addr("_dl_debug_state");
+                    self.r_debug = self.debug_data.get_symbol_addr("_r_debug");
+                }
+                Err(_) => eprintln!("Could not load shared library symbols for {}.", path),
+            }
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        if let Some(addr) = self.shlib_event.filter(|_| inferior.core().is_none()) {
+            if inferior.insert_breakpoint(addr).is_err() {
+                eprintln!("Could not set a breakpoint in the dynamic linker; shared libraries loaded later will be missed.");
+            }
+        }
+        self.load_libraries();
+    }
+
+    /// Brings our list of shared libraries up to date with the dynamic linker's, then resolves
+    /// the breakpoints that may now have somewhere to go
+    fn load_libraries(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let objects = self.r_debug.and_then(|r_debug| solib::read_link_map(inferior, r_debug));
+        if let Some(objects) = objects {
+            let interpreter = self.debug_data.interpreter().map(str::to_string);
+            self.debug_data.retain_libraries(|path| {
+                Some(path) == interpreter.as_deref() || objects.iter().any(|object| object.path == path)
+            });
+            for object in objects {
+                match self.debug_data.load_library(&object.path, object.bias) {
+                    Ok(true) => inferior.library_loaded(&object.path, object.bias),
+                    Ok(false) => {}
+                    Err(_) => eprintln!("Could not load shared library symbols for {}.", object.path),
+                }
+            }
+        }
+        self.resolve_breakpoints();
+    }
+
+    /// Resolves the location of every breakpoint again, against the symbols loaded now, and
+    /// inserts the enabled ones into the inferior. Breakpoints whose location doesn't resolve are
+    /// left pending.
+    fn resolve_breakpoints(&mut self) {
+        for idx in 0..self.breakpoints.len() {
+            let addr = self.resolve_location(&self.breakpoints[idx].spec);
+            let bp = &mut self.breakpoints[idx];
+            match addr {
+                Some(addr) => {
+                    bp.addr = addr;
+                    bp.pending = false;
+                    bp.func = self.debug_data.get_function_from_addr(addr);
+                    bp.line = self.debug_data.get_line_from_addr(addr);
+                }
+                None => bp.pending = true,
+            }
+        }
+        let addrs = self.enabled_breakpoint_addrs();
+        // Breakpoints set while looking at a core only take effect once the program runs
+        if let Some(inferior) = self.inferior.as_mut().filter(|inferior| inferior.core().is_none()) {
+            for addr in addrs {
+                if inferior.insert_breakpoint(addr).is_err() {
+                    eprintln!("Breakpoint Install failed!");
+                }
+            }
+        }
+    }
+
+    /// Addresses that should hold a 0xcc in the inferior: enabled breakpoints, and the return
+    /// addresses that tell when watchpoints on locals go out of scope
+    fn enabled_breakpoint_addrs(&self) -> Vec<usize> {
+        self.breakpoints
+            .iter()
+            .filter(|bp| bp.enabled && !bp.pending)
+            .map(|bp| bp.addr)
+            .chain(self.watchpoints.iter().filter_map(|wp| wp.scope).map(|scope| scope.return_addr))
+            .collect()
+    }
+
+    /// Turns a list of breakpoint numbers typed by the user into existing ids, complaining about
+    /// unknown ones. An empty list selects every breakpoint.
+    fn resolve_breakpoint_ids(&self, ids: &[usize]) -> Vec<usize> {
+        let all_ids = self
+            .breakpoints
+            .iter()
+            .map(|bp| bp.id)
+            .chain(self.watchpoints.iter().map(|wp| wp.id));
+        if ids.is_empty() {
+            return all_ids.collect();
+        }
+        let all_ids: Vec<usize> = all_ids.collect();
+        ids.iter()
+            .filter(|id| {
+                let exists = all_ids.contains(id);
+                if !exists {
+                    eprintln!("No breakpoint number {}.", id);
+                }
+                exists
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Enables or disables breakpoint `id`, patching the running inferior if there is one. The
+    /// original byte is only restored once no other enabled breakpoint shares the address.
+    fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) {
+        if let Some(wp) = self.watchpoints.iter_mut().find(|wp| wp.id == id) {
+            wp.enabled = enabled;
+            let wp = wp.clone();
+            if let Some(inferior) = self.inferior.as_mut() {
+                let result = if enabled {
+                    inferior.set_hw_watchpoint(wp.slot, wp.addr, wp.len, wp.kind)
+                } else {
+                    inferior.clear_hw_watchpoint(wp.slot)
+                };
+                if result.is_err() {
+                    eprintln!("Updating watchpoint {} in the inferior failed!", id);
+                }
+            }
+            return;
+        }
+        let addr = match self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            Some(bp) => {
+                bp.enabled = enabled;
+                if bp.pending {
+                    return;
+                }
+                bp.addr
+            }
+            None => return,
+        };
+        let still_needed = self.enabled_breakpoint_addrs().contains(&addr);
+        if let Some(inferior) = self.inferior.as_mut() {
+            let result = if still_needed {
+                inferior.insert_breakpoint(addr)
+            } else {
+                inferior.remove_breakpoint(addr).map_err(|_| ())
+            };
+            if result.is_err() {
+                eprintln!("Updating breakpoint {} in the inferior failed!", id);
+            }
+        }
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            println!("No breakpoints.");
+            return;
+        }
+        println!("{:<4} {:<4} {:<5} {:<18} What", "Num", "Enb", "Hits", "Address");
+        for bp in &self.breakpoints {
+            println!(
+                "{:<4} {:<4} {:<5} {:<18} {}",
+                bp.id,
+                if bp.enabled { "y" } else { "n" },
+                bp.hit_count,
+                if bp.pending { "<PENDING>".to_string() } else { format!("{:#x}", bp.addr) },
+                if bp.pending { bp.spec.clone() } else { bp.location() }
+            );
+            if let Some(condition) = &bp.condition {
+                println!("\tstop only if {}", condition);
+            }
+            if bp.ignore_count > 0 {
+                println!("\tWill ignore next {} crossings of breakpoint.", bp.ignore_count);
+            }
+            for command in &bp.commands {
+                println!("        {}", command);
+            }
+        }
+        for wp in &self.watchpoints {
+            println!(
+                "{:<4} {:<4} {:<5} {:<18} {}: {}",
+                wp.id,
+                if wp.enabled { "y" } else { "n" },
+                wp.hit_count,
+                format!("{:#x}", wp.addr),
+                wp.description(),
+                wp.expression
+            );
+        }
+    }
+
+    fn print_shared_libraries(&self) {
+        let libraries = self.debug_data.libraries();
+        if libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return;
+        }
+        println!("{:<19} {:<19} {:<11} Shared Object Library", "From", "To", "Syms Read");
+        for (path, from, to, has_debug_info) in &libraries {
+            println!(
+                "{:<19} {:<19} {:<11} {}",
+                format!("{:#018x}", from),
+                format!("{:#018x}", to),
+                if *has_debug_info { "Yes" } else { "Yes (*)" },
+                path
+            );
+        }
+        if libraries.iter().any(|(_, _, _, has_debug_info)| !has_debug_info) {
+            println!("(*): Shared library is missing debugging information.");
+        }
+    }
+
+    /// Figures out the address and type of a watch expression: anything `print` understands
+    /// that lives in memory, or `*addr`
+    fn resolve_watch_location(&self, expression: &str) -> Result<(usize, Type), String> {
+        let place = match self.inferior {
+            Some(_) => self.evaluate(expression).and_then(|value| match value.address {
+                Some(addr) => Ok((addr, value.ty)),
+                None => Err(format!("Cannot watch constant value `{}'.", expression)),
+            }),
+            // Before the program runs, there is nothing to evaluate against but globals
+            None => match self.debug_data.get_global_variable(expression) {
+                Some(Variable { location: Location::Address(addr), entity_type, .. }) => {
+                    Ok((*addr, entity_type.clone()))
+                }
+                _ => Err(format!("No symbol \"{}\" in current context.", expression)),
+            },
+        };
+        if place.is_ok() {
+            return place;
+        }
+        match expression.strip_prefix('*').and_then(Debugger::parse_address) {
+            Some(addr) => {
+                // Watch as much as the alignment of the address allows
+                let len = [8, 4, 2, 1].into_iter().find(|len| addr % len == 0).unwrap();
+                Ok((addr, Type::new("long unsigned int".to_string(), len)))
+            }
+            None => place,
+        }
+    }
+
+    /// An evaluator for expressions in the selected frame, if there is an inferior to evaluate
+    /// them against
+    fn evaluator(&self) -> Option<Evaluator<'_>> {
+        let inferior = self.inferior.as_ref()?;
+        Some(Evaluator::new(&self.debug_data, inferior, self.selected_frame()))
+    }
+
+    /// Evaluates an expression in the selected frame
+    fn evaluate(&self, expression: &str) -> Result<expression::Value, String> {
+        let evaluator = self.evaluator().ok_or("No existing inferior is running!")?;
+        evaluator.evaluate(&expression::parse(expression, &self.debug_data)?)
+    }
+
+    /// Unwinds the stack of the running inferior and returns the selected frame
+    fn selected_frame(&self) -> Option<Frame> {
+        let inferior = self.inferior.as_ref()?;
+        let mut frames = inferior.backtrace(&self.debug_data).ok()?;
+        if self.selected_frame < frames.len() {
+            Some(frames.swap_remove(self.selected_frame))
+        } else {
+            None
+        }
+    }
+
+    /// Implements `up`, `down` and `frame`: selects frame `level` and describes it, or prints
+    /// `out_of_range` if there is no such frame
+    fn select_frame(&mut self, level: usize, out_of_range: &str) {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                eprintln!("No stack.");
+                return;
+            }
+        };
+        let frames = match inferior.backtrace(&self.debug_data) {
+            Ok(frames) => frames,
+            Err(_) => {
+                eprintln!("Backtrace failed!");
+                return;
+            }
+        };
+        match frames.get(level) {
+            Some(frame) => {
+                self.selected_frame = level;
+                self.last_listed = None;
+                println!("{}", inferior.describe_frame(&self.debug_data, frame));
+            }
+            None => eprintln!("{}", out_of_range),
+        }
+    }
+
+    /// The source line of the selected frame
+    fn selected_line(&self) -> Option<Line> {
+        self.selected_frame()?.function(&self.debug_data).1
+    }
+
+    /// Resolves the argument of `list` to a source line: `<line>`, `<function>`, `<file>:<line>`
+    /// or `<file>:<function>`. Bare line numbers refer to the file listed last.
+    fn resolve_source_line(&self, spec: &str) -> Option<Line> {
+        let (file, location) = match split_file_location(spec) {
+            Some((file, location)) => (Some(file), location),
+            None => (None, spec),
+        };
+        if let Ok(number) = location.parse::<usize>() {
+            let path = match (file, &self.last_listed) {
+                (Some(file), _) => self.debug_data.get_source_path(Some(file))?,
+                (None, Some((path, _))) => path.clone(),
+                (None, None) => match self.selected_line() {
+                    Some(line) => line.file,
+                    None => self.debug_data.get_source_path(None)?,
+                },
+            };
+            return Some(Line { file: path, number, address: 0 });
+        }
+        let addr = self.debug_data.get_addr_for_function(file, location)?;
+        self.debug_data.get_line_from_addr(addr)
+    }
+
+    /// Implements `list`: prints ten source lines, marking the selected frame's line with `>`
+    /// and lines with breakpoints with `*`
+    fn list_source(&mut self, spec: Option<&str>) {
+        let (path, first) = match (spec, &self.last_listed) {
+            (Some(spec), _) => match self.resolve_source_line(spec) {
+                Some(line) => (line.file, line.number.saturating_sub(5).max(1)),
+                None => {
+                    match split_file_location(spec) {
+                        Some((file, _)) if self.debug_data.get_source_path(Some(file)).is_none() => {
+                            eprintln!("No source file named {}.", file)
+                        }
+                        _ => eprintln!("Function \"{}\" not defined.", spec),
+                    }
+                    return;
+                }
+            },
+            (None, Some((path, last))) => (path.clone(), last + 1),
+            (None, None) => {
+                let line = self.selected_line().or_else(|| {
+                    let addr = self.debug_data.get_addr_for_function(None, "main")?;
+                    self.debug_data.get_line_from_addr(addr)
+                });
+                match line {
+                    Some(line) => (line.file, line.number.saturating_sub(5).max(1)),
+                    None => {
+                        eprintln!("No source file to list.");
+                        return;
+                    }
+                }
+            }
+        };
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                return;
+            }
+        };
+        let lines: Vec<&str> = source.lines().collect();
+        if first > lines.len() {
+            eprintln!("Line number {} out of range; \"{}\" has {} lines.", first, path, lines.len());
+            return;
+        }
+        let current = self.selected_line();
+        let last = (first + 9).min(lines.len());
+        for number in first..=last {
+            let is_current = current.as_ref().is_some_and(|line| line.file == path && line.number == number);
+            let has_breakpoint = self.breakpoints.iter().any(|bp| {
+                bp.line.as_ref().is_some_and(|line| line.file == path && line.number == number)
+            });
+            println!(
+                "{}{} {:<4}{}",
+                if has_breakpoint { '*' } else { ' ' },
+                if is_current { '>' } else { ' ' },
+                number,
+                lines[number - 1]
+            );
+        }
+        self.last_listed = Some((path, last));
+    }
+
+    /// Evaluates the address operand of `x`: any expression, whose value (or address, for an
+    /// array) is where to start
+    fn evaluate_address(&self, expression: &str) -> Result<usize, String> {
+        let evaluator = self.evaluator().ok_or("No existing inferior is running!")?;
+        let value = evaluator.evaluate(&expression::parse(expression, &self.debug_data)?)?;
+        Ok(evaluator.integer(&value)? as usize)
+    }
+
+    /// Implements `info threads`
+    fn print_threads(&self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        println!("  Id   Target Id          Frame");
+        for thread in inferior.threads() {
+            let location = match inferior.thread_pc(thread.tid) {
+                Ok(pc) => {
+                    let func = self.debug_data.get_function_from_addr(pc);
+                    match self.debug_data.get_line_from_addr(pc) {
+                        Some(line) => format!("{} () at {}", func.as_deref().unwrap_or("??"), line),
+                        None => format!("{:#018x} in {} ()", pc, func.as_deref().unwrap_or("??")),
+                    }
+                }
+                Err(err) => format!("<unavailable: {}>", err),
+            };
+            println!(
+                "{} {:<4} {:<18} {}",
+                if thread.tid == inferior.tid() { '*' } else { ' ' },
+                thread.number,
+                format!("Thread {}", thread.tid),
+                location
+            );
+        }
+    }
+
+    /// Implements `info registers`
+    fn print_registers(&self, names: &[String]) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let registers = match inferior.get_registers() {
+            Ok(registers) => registers,
+            Err(_) => {
+                eprintln!("Reading registers failed!");
+                return;
+            }
+        };
+        for name in names {
+            if !registers.iter().any(|(reg, _)| reg == name) {
+                eprintln!("Invalid register \"{}\"", name);
+            }
+        }
+        // Outer frames have only the registers the unwinder could recover
+        let frame = match self.selected_frame {
+            0 => None,
+            _ => self.selected_frame(),
+        };
+        for (name, value) in registers {
+            if names.is_empty() || names.iter().any(|wanted| wanted == name) {
+                let value = match frame.as_ref().and_then(|frame| frame.registers.get_by_name(name)) {
+                    Some(Some(value)) => value,
+                    Some(None) => {
+                        println!("{:<15}<not saved>", name);
+                        continue;
+                    }
+                    None => value,
+                };
+                let natural = match name {
+                    "rip" => format!("{:#x}{}", value, self.symbolize(value as usize)),
+                    _ => format_register(name, value),
+                };
+                println!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural);
+            }
+        }
+    }
+
+    /// Implements `x`: dumps `count` units of `size` bytes starting at `addr`
+    fn examine_memory(&self, addr: usize, count: usize, format: char, size: usize) {
+        let inferior = self.inferior.as_ref().unwrap();
+        if format == 'i' {
+            self.examine_instructions(addr, count);
+            return;
+        }
+        if format == 's' {
+            let mut addr = addr;
+            for _ in 0..count {
+                match inferior.read_c_string(addr, 200) {
+                    Ok(string) => {
+                        println!("{:#x}:\t{:?}", addr, string);
+                        addr += string.len() + 1;
+                    }
+                    Err(_) => {
+                        eprintln!("Cannot access memory at address {:#x}", addr);
+                        return;
+                    }
+                }
+            }
+            return;
+        }
+        let per_line = match (format, size) {
+            ('c', _) | (_, 1) | (_, 2) => 8,
+            (_, 4) => 4,
+            _ => 2,
+        };
+        let bytes = match inferior.read_memory(addr, count * size) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                eprintln!("Cannot access memory at address {:#x}", addr);
+                return;
+            }
+        };
+        for (line_idx, line) in bytes.chunks(per_line * size).enumerate() {
+            let line_addr = addr + line_idx * per_line * size;
+            print!("{:#x}{}:", line_addr, self.symbolize(line_addr));
+            for unit in line.chunks(size) {
+                let unsigned = Type::new("unsigned".to_string(), size).value_as_i64(unit) as u64;
+                let signed = Type::new("signed".to_string(), size).value_as_i64(unit);
+                let formatted = match format {
+                    'd' => signed.to_string(),
+                    'u' => unsigned.to_string(),
+                    'o' => format!("0{:o}", unsigned),
+                    't' => format!("{:0width$b}", unsigned, width = size * 8),
+                    'c' => Type::new("char".to_string(), 1).format_value(&unit[..1]),
+                    _ => format!("{:#0width$x}", unsigned, width = size * 2 + 2),
+                };
+                print!("\t{}", formatted);
+            }
+            println!();
+        }
+    }
+
+    /// Implements `x/i`: disassembles `count` instructions starting at `addr`
+    fn examine_instructions(&self, addr: usize, count: usize) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let bytes = match inferior.read_memory(addr, count * disassembler::MAX_INSTRUCTION_LEN) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                eprintln!("Cannot access memory at address {:#x}", addr);
+                return;
+            }
+        };
+        let pc = self.selected_frame().map(|frame| frame.pc);
+        for instruction in disassembler::decode(&bytes, addr, count) {
+            println!(
+                "{} {:#x}{}:\t{}",
+                if Some(instruction.address) == pc { "=>" } else { "  " },
+                instruction.address,
+                self.symbolize(instruction.address),
+                self.describe_instruction(&instruction)
+            );
+        }
+    }
+
+    /// Implements `disassemble`: dumps the whole function containing the selected frame's pc, the
+    /// named function, or the function containing the given address. Each source line is shown
+    /// before its first instruction.
+    fn disassemble(&self, spec: Option<&str>) {
+        let addr = match spec {
+            None => self.selected_frame().map(|frame| frame.lookup_pc()),
+            Some(spec) => self
+                .debug_data
+                .get_addr_for_function(None, spec)
+                .or_else(|| Debugger::parse_address(spec.trim_start_matches('*'))),
+        };
+        let func = match addr.and_then(|addr| self.debug_data.get_function_containing_addr(addr)) {
+            Some(func) => func,
+            None => {
+                eprintln!("No function contains specified address.");
+                return;
+            }
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let bytes = match inferior.read_memory(func.address, func.text_length) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                eprintln!("Cannot access memory at address {:#x}", func.address);
+                return;
+            }
+        };
+        let pc = self.selected_frame().map(|frame| frame.pc);
+        let mut source = None;
+        let mut current_line = None;
+        println!("Dump of assembler code for function {}:", func.name);
+        for instruction in disassembler::decode(&bytes, func.address, usize::MAX) {
+            let line = self.debug_data.get_line_from_addr(instruction.address);
+            if let Some(line) = line.filter(|line| Some(line.number) != current_line) {
+                let text = source
+                    .get_or_insert_with(|| fs::read_to_string(&line.file).unwrap_or_default())
+                    .lines()
+                    .nth(line.number - 1)
+                    .unwrap_or("")
+                    .to_string();
+                println!("{}\t{}", line, text);
+                current_line = Some(line.number);
+            }
+            println!(
+                "{} {:#018x} <+{}>:\t{}",
+                if Some(instruction.address) == pc { "=>" } else { "  " },
+                instruction.address,
+                instruction.address - func.address,
+                self.describe_instruction(&instruction)
+            );
+        }
+        println!("End of assembler dump.");
+    }
+
+    /// Instruction text, with the function a direct jump or call goes to
+    fn describe_instruction(&self, instruction: &disassembler::Instruction) -> String {
+        match instruction.branch_target {
+            Some(target) => format!("{}{}", instruction.text, self.symbolize(target)),
+            None => instruction.text.clone(),
+        }
+    }
+
+    /// Describes a code address as ` <function+offset>`, or an empty string if it isn't in any
+    /// known function
+    fn symbolize(&self, addr: usize) -> String {
+        match self.debug_data.get_function_containing_addr(addr) {
+            Some(func) if addr == func.address => format!(" <{}>", func.name),
+            Some(func) => format!(" <{}+{}>", func.name, addr - func.address),
+            None => String::new(),
+        }
+    }
+
+    /// Implements `set var` and `set $reg`: evaluates the new value and stores it, converted to
+    /// the type of whatever is being assigned to
+    fn set_value(&mut self, target: &str, value: &str) -> Result<(), String> {
+        let evaluator = self.evaluator().ok_or("No existing inferior is running!")?;
+        let new_value = evaluator.evaluate(&expression::parse(value, &self.debug_data)?)?;
+        if let Some(register) = target.strip_prefix('$') {
+            let new_value = evaluator.integer(&new_value)?;
+            return match self.inferior.as_mut().unwrap().set_register(register, new_value as u64) {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(format!("Invalid register \"{}\"", register)),
+                Err(_) => Err("Writing register failed!".to_string()),
+            };
+        }
+        let place = evaluator.evaluate(&expression::parse(target, &self.debug_data)?)?;
+        let addr = place.address.ok_or("Left operand of assignment is not an lvalue.")?;
+        let bytes = evaluator.convert(&new_value, &place.ty)?;
+        self.inferior
+            .as_mut()
+            .unwrap()
+            .write_memory(addr, &bytes)
+            .map_err(|_| format!("Writing {} failed!", target))
+    }
+
+    fn add_watchpoint(&mut self, expression: &str, kind: WatchKind) {
+        let (addr, entity_type) = match self.resolve_watch_location(expression) {
+            Ok(location) => location,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        };
+        let len = entity_type.size;
+        if ![1, 2, 4, 8].contains(&len) || addr % len != 0 {
+            eprintln!("Can't watch {} bytes at {:#x} with a hardware watchpoint.", len, addr);
+            return;
+        }
+        let slot = match (0..4).find(|slot| self.watchpoints.iter().all(|wp| wp.slot != *slot)) {
+            Some(slot) => slot,
+            None => {
+                eprintln!("Hardware watchpoints used exceeds limit of 4.");
+                return;
+            }
+        };
+        let scope = self.watch_scope(expression);
+        let mut old_value = Vec::new();
+        if let Some(inferior) = self.inferior.as_mut() {
+            if inferior.set_hw_watchpoint(slot, addr, len, kind).is_err() {
+                eprintln!("Couldn't set hardware watchpoint at {:#x}", addr);
+                return;
+            }
+            if let Some(scope) = scope {
+                if inferior.insert_breakpoint(scope.return_addr).is_err() {
+                    eprintln!("Couldn't set a breakpoint at {:#x} to tell when {} goes out of scope", scope.return_addr, expression);
+                }
+            }
+            old_value = inferior.read_memory(addr, len).unwrap_or_default();
+        }
+        let wp = Watchpoint {
+            id: self.next_breakpoint_id,
+            expression: expression.to_string(),
+            addr,
+            len,
+            kind,
+            slot,
+            entity_type,
+            old_value,
+            hit_count: 0,
+            enabled: true,
+            scope,
+        };
+        self.next_breakpoint_id += 1;
+        println!("{} {}: {}", wp.description(), wp.id, wp.expression);
+        self.watchpoints.push(wp);
+    }
+
+    /// If a watch expression involves locals of the selected frame, returns the frame they
+    /// belong to
+    fn watch_scope(&self, expression: &str) -> Option<WatchScope> {
+        let evaluator = self.evaluator()?;
+        let expr = expression::parse(expression, &self.debug_data).ok()?;
+        if !evaluator.uses_frame(&expr) {
+            return None;
+        }
+        let cfa = evaluator.frame()?.cfa?;
+        // The return address sits just below the CFA
+        let inferior = self.inferior.as_ref()?;
+        let return_addr = inferior.read_memory(cfa - 8, 8).ok()?;
+        Some(WatchScope {
+            tid: inferior.tid(),
+            cfa,
+            return_addr: u64::from_le_bytes(return_addr.try_into().ok()?) as usize,
+        })
+    }
+
+    /// Deletes the watchpoints for which `predicate` holds, clearing their debug registers and
+    /// any breakpoints that were only there to notice them going out of scope
+    fn delete_watchpoints(&mut self, predicate: impl Fn(&Watchpoint) -> bool) {
+        let (deleted, kept) = self.watchpoints.drain(..).partition(|wp| predicate(wp));
+        self.watchpoints = kept;
+        let still_needed = self.enabled_breakpoint_addrs();
+        let inferior = match self.inferior.as_mut().filter(|inferior| inferior.core().is_none()) {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        for wp in deleted {
+            inferior.clear_hw_watchpoint(wp.slot).ok();
+            if let Some(scope) = wp.scope.filter(|scope| !still_needed.contains(&scope.return_addr)) {
+                inferior.remove_breakpoint(scope.return_addr).ok();
+            }
+        }
+    }
+
+    /// Deletes the watchpoints on locals whose frame has returned, or with `all`, every watchpoint
+    /// on locals (when the process they were in is gone). Returns true if any were deleted.
+    fn delete_out_of_scope_watchpoints(&mut self, all: bool) -> bool {
+        let current = self.inferior.as_ref().and_then(|inferior| {
+            let rsp = inferior.get_register("rsp").ok()??;
+            Some((inferior.tid(), rsp as usize))
+        });
+        let out_of_scope = |wp: &Watchpoint| match (wp.scope, current) {
+            (None, _) => false,
+            (Some(scope), Some((tid, rsp))) if !all => tid == scope.tid && rsp >= scope.cfa,
+            (Some(_), _) => all,
+        };
+        let ids: Vec<usize> = self.watchpoints.iter().filter(|wp| out_of_scope(wp)).map(|wp| wp.id).collect();
+        for id in &ids {
+            println!();
+            println!("Watchpoint {} deleted because the program has left the block in", id);
+            println!("which its expression is valid.");
+        }
+        self.delete_watchpoints(|wp| ids.contains(&wp.id));
+        !ids.is_empty()
+    }
+
+    /// Returns true if `addr` is where the frame of a watchpoint on locals returns to, but the
+    /// frame is still there (a recursive call of the same function returned)
+    fn watch_scope_still_active(&self, addr: usize) -> bool {
+        let inferior = self.inferior.as_ref().unwrap();
+        let rsp = match inferior.get_register("rsp") {
+            Ok(Some(rsp)) => rsp as usize,
+            _ => return false,
+        };
+        self.watchpoints
+            .iter()
+            .filter_map(|wp| wp.scope)
+            .any(|scope| scope.return_addr == addr && (inferior.tid() != scope.tid || rsp < scope.cfa))
+    }
+
+    /// Applies our watchpoints and settings to a new inferior
+    fn configure_inferior(&mut self) {
+        self.arm_watchpoints();
+        let inferior = self.inferior.as_mut().unwrap();
+        inferior.set_signal_table(self.signals.clone());
+        inferior.set_follow_fork_mode(self.follow_fork_mode);
+    }
+
+    /// Programs the debug registers of a freshly started inferior with our enabled watchpoints
+    fn arm_watchpoints(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        for wp in self.watchpoints.iter_mut().filter(|wp| wp.enabled) {
+            if inferior.set_hw_watchpoint(wp.slot, wp.addr, wp.len, wp.kind).is_err() {
+                eprintln!("Couldn't insert watchpoint {}", wp.id);
+            }
+            wp.old_value = inferior.read_memory(wp.addr, wp.len).unwrap_or_default();
+        }
+    }
+
+    /// If the inferior stopped because of a watchpoint, prints what happened to the watched
+    /// location. Returns true if that was the case.
+    fn report_watchpoint_hit(&mut self) -> bool {
+        let inferior = self.inferior.as_mut().unwrap();
+        let slot = match inferior.take_watchpoint_hit() {
+            Ok(Some(slot)) => slot,
+            _ => return false,
+        };
+        let wp = match self.watchpoints.iter_mut().find(|wp| wp.enabled && wp.slot == slot) {
+            Some(wp) => wp,
+            None => return false,
+        };
+        let new_value = inferior.read_memory(wp.addr, wp.len).unwrap_or_default();
+        wp.hit_count += 1;
+        println!();
+        println!("{} {}: {}", wp.description(), wp.id, wp.expression);
+        println!();
+        // rwatch only stops on reads (the inferior filters out writes), so there's no old value
+        if wp.kind != WatchKind::Read && new_value != wp.old_value {
+            println!("Old value = {}", wp.entity_type.format_value(&wp.old_value));
+            println!("New value = {}", wp.entity_type.format_value(&new_value));
+        } else {
+            println!("Value = {}", wp.entity_type.format_value(&new_value));
+        }
+        wp.old_value = new_value;
+        true
+    }
+
     /// This function encapsualte inferior.continue_execute() to Debugger::inferior_continue_execute
     /// can print status of inferior according to its signal 
     pub fn inferior_continue_execute(&mut self) -> Result<(), ()>{
-        match self.inferior.as_mut().unwrap().continue_execute() {
+        loop {
+            let breakpoints = &mut self.breakpoints;
+            let debug_data = &self.debug_data;
+            let mut triggered = None;
+            let result = self.inferior.as_mut().unwrap().continue_execute(&mut |inferior, addr| {
+                match Debugger::breakpoint_should_stop(breakpoints, debug_data, inferior, addr) {
+                    Some(ids) => {
+                        triggered = Some((addr, ids));
+                        true
+                    }
+                    None => false,
+                }
+            });
+            // The dynamic linker has changed its list of libraries: catch up and keep going
+            match result {
+                Ok(Status::Stopped(Signal::SIGTRAP, addr)) if Some(addr) == self.shlib_event => {
+                    self.load_libraries()
+                }
+                // Only there to catch a watchpoint's frame returning, which it hasn't yet
+                Ok(Status::Stopped(Signal::SIGTRAP, addr))
+                    if matches!(&triggered, Some((_, ids)) if ids.is_empty())
+                        && self.watch_scope_still_active(addr) => {}
+                _ => return self.report_status(result, triggered),
+            }
+        }
+    }
+
+    /// Prints the status of the inferior after it has been resumed (by continuing or stepping),
+    /// dropping it if it is no longer alive. `triggered` is the address of the last breakpoint
+    /// stop and the ids of the breakpoints that caused it, as `breakpoint_should_stop` decided.
+    fn report_status(
+        &mut self,
+        result: Result<Status, nix::Error>,
+        triggered: Option<(usize, Vec<usize>)>,
+    ) -> Result<(), ()> {
+        self.selected_frame = 0;
+        self.last_listed = None;
+        match result {
             Ok(continue_res) => {
                 match continue_res {
                     Status::Stopped(stopped_signal, cur_addr) => {
+                        let mut commands = Vec::new();
+                        let mut hit_breakpoints = Vec::new();
+                        let mut reason = match stopped_signal {
+                            Signal::SIGTRAP => "end-stepping-range",
+                            _ => "signal-received",
+                        };
+                        if self.delete_out_of_scope_watchpoints(false) {
+                            reason = "watchpoint-scope";
+                        }
+                        if stopped_signal == Signal::SIGTRAP && self.report_watchpoint_hit() {
+                            reason = "watchpoint-trigger";
+                        } else if stopped_signal == Signal::SIGTRAP {
+                            hit_breakpoints = match triggered {
+                                Some((addr, ids)) if addr == cur_addr => ids,
+                                // Stepping ended on a breakpoint without trapping on it
+                                _ => Debugger::breakpoint_should_stop(
+                                    &mut self.breakpoints,
+                                    &self.debug_data,
+                                    self.inferior.as_ref().unwrap(),
+                                    cur_addr,
+                                )
+                                .unwrap_or_default(),
+                            };
+                            for bp in self.breakpoints.iter().filter(|bp| hit_breakpoints.contains(&bp.id)) {
+                                println!("Breakpoint {}, {}", bp.id, bp.location());
+                                commands.extend(bp.commands.iter().cloned());
+                                reason = "breakpoint-hit";
+                            }
+                        }
                         self.print_stopped_location(stopped_signal, cur_addr);
+                        self.show_displays();
+                        let event = self
+                            .interpreter
+                            .is_some()
+                            .then(|| self.stop_event(reason, stopped_signal, cur_addr, &hit_breakpoints));
+                        if let (Some(interpreter), Some(event)) = (self.interpreter.as_mut(), event) {
+                            interpreter.emit(event);
+                        }
+                        // Breakpoint commands run before whatever was queued up
+                        for command in commands.into_iter().rev() {
+                            self.pending_lines.push_front(command);
+                        }
                     },
+                    Status::Execed(exe) => {
+                        self.delete_out_of_scope_watchpoints(true);
+                        return self.follow_exec(&exe);
+                    }
                     Status::Exited(exit_code) => {
+                        self.delete_out_of_scope_watchpoints(true);
                         println!("Child exited (status {})", exit_code);
                         self.inferior = None;
+                        if let Some(interpreter) = self.interpreter.as_mut() {
+                            interpreter.emit(json!({"type": "event", "event": "exited", "status": exit_code}));
+                        }
+                    },
+                    Status::Signaled(signaled_signal) => {
+                        self.delete_out_of_scope_watchpoints(true);
+                        println!("Child exited due to signal {}", signaled_signal);
+                        self.inferior = None;
+                        if let Some(interpreter) = self.interpreter.as_mut() {
+                            interpreter.emit(json!({
+                                "type": "event",
+                                "event": "exited",
+                                "signal": signaled_signal.as_str(),
+                            }));
+                        }
                     },
-                    Status::Signaled(signaled_signal) => println!("Child exited due to signal {}", signaled_signal),
                 };
                 Ok(())
             },
@@ -151,41 +1668,157 @@ impl Debugger {
         }
     }
 
+    /// Describes a stop of the inferior for the JSON interpreter: why and where it stopped, and the
+    /// frames of the current thread
+    fn stop_event(&self, reason: &str, signal: Signal, addr: usize, breakpoints: &[usize]) -> Value {
+        let inferior = self.inferior.as_ref().unwrap();
+        let tid = inferior.tid();
+        let thread = inferior.threads().iter().find(|thread| thread.tid == tid);
+        let frames: Vec<Value> = inferior
+            .backtrace(&self.debug_data)
+            .unwrap_or_default()
+            .iter()
+            .map(|frame| {
+                let (function, line) = frame.function(&self.debug_data);
+                json!({
+                    "level": frame.level,
+                    "address": format!("{:#x}", frame.pc),
+                    "function": function,
+                    "file": line.as_ref().map(|line| line.file.clone()),
+                    "line": line.as_ref().map(|line| line.number),
+                    "inlined": frame.inline_depth > 0,
+                })
+            })
+            .collect();
+        let line = self.debug_data.get_line_from_addr(addr);
+        json!({
+            "type": "event",
+            "event": "stopped",
+            "reason": reason,
+            "signal": signal.as_str(),
+            "address": format!("{:#x}", addr),
+            "function": self.debug_data.get_function_from_addr(addr),
+            "file": line.as_ref().map(|line| line.file.clone()),
+            "line": line.as_ref().map(|line| line.number),
+            "breakpoints": breakpoints,
+            "thread": thread.map(|thread| thread.number),
+            "tid": tid.as_raw(),
+            "frames": frames,
+        })
+    }
+
     /// This function prints the reason why the process stop(by which signal) 
     /// and current stopped location & function & line number(if is_some())
     pub fn print_stopped_location(&mut self, stopped_signal: Signal, cur_addr: usize) {
         println!("Child stopped (signal {})", stopped_signal.as_str());
+        if matches!(stopped_signal, Signal::SIGSEGV | Signal::SIGBUS) {
+            if let Some(Ok(addr)) = self.inferior.as_ref().map(|inferior| inferior.fault_address()) {
+                println!("Fault address: {:#x}", addr);
+            }
+        }
         let debug_current_line = self.debug_data.get_line_from_addr(cur_addr);
-        let debug_current_func = self.debug_data.get_function_from_addr(cur_addr);
-        if debug_current_line.is_some() && debug_current_func.is_some() {
-            let func_name = debug_current_func.as_ref().unwrap();
-            let file_name = &debug_current_line.as_ref().unwrap().file;
-            let code_line = debug_current_line.as_ref().unwrap().number;
-            println!("Stopped at {} ({}:{})", func_name, file_name, code_line);
+        let chain = self.debug_data.get_inline_chain(cur_addr);
+        if let (Some(line), Some((func_name, _))) = (debug_current_line, chain.first()) {
+            let mut location = format!("Stopped at {} ({}:{})", func_name, line.file, line.number);
+            // Functions inlined into others are shown with the calls they were inlined at
+            for (caller, call) in &chain[1..] {
+                location += &format!(", inlined into {}", caller);
+                if let Some(call) = call {
+                    location += &format!(" ({}:{})", call.file, call.number);
+                }
+            }
+            println!("{}", location);
         }
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
+    /// Returns the instruction pointer of the stopped inferior, printing an error if there is none
+    fn current_pc(&self) -> Option<usize> {
+        match &self.inferior {
+            Some(inferior) => match inferior.thread_pc(inferior.tid()) {
+                Ok(rip) => Some(rip),
+                Err(_) => {
+                    eprintln!("Reading registers failed!");
+                    None
+                }
+            },
+            None => {
+                eprintln!("No existing inferior is running!");
+                None
+            }
+        }
+    }
+
+    /// Reads `var` from the selected frame and prints it as `name = value`
+    fn print_variable(&self, evaluator: &Evaluator, var: &Variable) {
+        match evaluator.variable_value(var) {
+            Ok(value) => println!("{} = {}", var.name, self.format_value(&value)),
+            Err(err) => println!("{} = <{}>", var.name, err),
+        }
+    }
+
+    /// Formats a value the way `print` shows it. C strings are shown after the pointer to them.
+    fn format_value(&self, value: &expression::Value) -> String {
+        if value.optimized_out {
+            return "<optimized out>".to_string();
+        }
+        let inferior = self.inferior.as_ref().unwrap();
+        let read_memory = |addr, len| inferior.read_memory(addr, len).ok();
+        let mut formatted = ValueFormatter::new(&self.debug_data, &read_memory).format(&value.ty, &value.bytes);
+        if value.ty.name.ends_with("char *") {
+            let addr = usize::from_le_bytes(value.bytes[..8].try_into().unwrap());
+            if let Ok(string) = inferior.read_c_string(addr, 200) {
+                formatted = format!("{} {:?}", formatted, string);
+            }
+        }
+        formatted
+    }
+
+    /// Shows the expressions added with `display`
+    fn show_displays(&self) {
+        if self.inferior.is_some() {
+            for display in &self.displays {
+                self.show_display(display);
+            }
+        }
+    }
+
+    /// Shows one `display` expression, e.g. `1: x = 5`
+    fn show_display(&self, (id, expression, expr): &(usize, String, Expr)) {
+        let value = self
+            .evaluator()
+            .ok_or("No existing inferior is running!".to_string())
+            .and_then(|evaluator| evaluator.evaluate(expr));
+        match value {
+            Ok(value) => println!("{}: {} = {}", id, expression, self.format_value(&value)),
+            Err(err) => println!("{}: {} = <error: {}>", id, expression, err),
+        }
+    }
+
+    /// Returns the next line of input: a pending line from a command file or breakpoint command
+    /// list if there is one, otherwise a line typed by the user. Returns None at end of input (or
+    /// once the pending lines run out, in batch mode).
+    fn next_line(&mut self, prompt: &str) -> Option<String> {
+        if let Some(line) = self.pending_lines.pop_front() {
+            return Some(line);
+        }
+        if self.batch {
+            return None;
+        }
+        if let Some(interpreter) = self.interpreter.as_mut() {
+            return interpreter.read_command();
+        }
         loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
+            match self.readline.readline(prompt) {
                 Err(ReadlineError::Interrupted) => {
                     // User pressed ctrl+c. We're going to ignore it
                     println!("Type \"quit\" to exit");
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
-                }
+                Err(ReadlineError::Eof) => return None,
                 Err(err) => {
                     panic!("Unexpected I/O error: {:?}", err);
                 }
                 Ok(line) => {
-                    if line.trim().len() == 0 {
+                    if line.trim().is_empty() {
                         continue;
                     }
                     self.readline.add_history_entry(line.as_str()).ok();
@@ -195,23 +1828,79 @@ impl Debugger {
                             self.history_path, err
                         );
                     }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
-                    }
+                    return Some(line);
                 }
             }
         }
     }
 
+    /// Reads the body of a `commands` block, up to (not including) the line saying `end`
+    fn read_command_list(&mut self) -> Vec<String> {
+        let mut commands = Vec::new();
+        while let Some(line) = self.next_line(">") {
+            let line = line.trim();
+            if line == "end" {
+                break;
+            }
+            commands.push(line.to_string());
+        }
+        commands
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            if let Some(interpreter) = self.interpreter.as_mut() {
+                interpreter.finish_command();
+            }
+            // End of input (ctrl+d, or the end of a batch script) is the equivalent of "quit"
+            let from_request = self.pending_lines.is_empty();
+            let line = match self.next_line("(deet) ") {
+                Some(line) => line,
+                None => return DebuggerCommand::Quit,
+            };
+            if let Some(interpreter) = self.interpreter.as_mut() {
+                interpreter.start_command(&line, from_request);
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                return cmd;
+            } else {
+                eprintln!("Unrecognized command.");
+            }
+        }
+    }
+
     pub fn parse_address(addr: &str) -> Option<usize> {
         let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
             &addr[2..]
         } else {
-            &addr
+            addr
         };
         usize::from_str_radix(addr_without_0x, 16).ok()
     }
 }
+
+/// Formats a register value the way `info registers` shows it in its last column: addresses in
+/// hex, everything else in decimal
+fn format_register(name: &str, value: u64) -> String {
+    match name {
+        "rip" | "pc" | "rsp" | "sp" | "rbp" | "fs_base" | "gs_base" | "eflags" => {
+            format!("{:#x}", value)
+        }
+        _ => (value as i64).to_string(),
+    }
+}
+
+/// Splits a `file:location` spec at its colon. A `::` separates the parts of a path (as in
+/// `hello::main`) instead.
+fn split_file_location(spec: &str) -> Option<(&str, &str)> {
+    let bytes = spec.as_bytes();
+    let colon = (0..bytes.len()).find(|&i| {
+        bytes[i] == b':'
+            && bytes.get(i + 1) != Some(&b':')
+            && (i == 0 || bytes[i - 1] != b':')
+    })?;
+    Some((&spec[..colon], &spec[colon + 1..]))
+}
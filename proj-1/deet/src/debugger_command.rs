@@ -1,13 +1,70 @@
+use crate::breakpoint::WatchKind;
+use crate::inferior::FollowForkMode;
+
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
     Continue,
     Backtrace,
-    Breakpoint(String),
+    /// Location and optional condition (the text after `if`)
+    Breakpoint(String, Option<String>),
+    Step,
+    Next,
+    StepInstruction,
+    Finish,
+    Print(String),
+    /// `display [<expression>]`: show an expression whenever the inferior stops; without one,
+    /// show them all now
+    Display(Option<String>),
+    /// `undisplay [N...]`, all of them if none are given
+    Undisplay(Vec<usize>),
+    InfoDisplay,
+    InfoLocals,
+    InfoArgs,
+    InfoBreakpoints,
+    InfoSharedLibrary,
+    Delete(Vec<usize>),
+    Disable(Vec<usize>),
+    Enable(Vec<usize>),
+    Ignore(usize, usize),
+    Watch(String, WatchKind),
+    Attach(i32),
+    Detach,
+    /// Registers to show; all of them if empty
+    InfoRegisters(Vec<String>),
+    /// `x/<count><format><unit size> <address>`
+    Examine(usize, char, usize, String),
+    /// `set var <variable> = <value>` or `set $<register> = <value>`
+    Set(String, String),
+    /// Select a frame further out (`up`) or in (`down`) by the given number of levels
+    Up(usize),
+    Down(usize),
+    /// `frame N` selects frame N; plain `frame` describes the selected frame
+    Frame(Option<usize>),
+    /// `list [<line> | <function> | <file>:<line> | <file>:<function>]`
+    List(Option<String>),
+    /// `disassemble [<function> | <address>]`
+    Disassemble(Option<String>),
+    /// `handle <signal> [stop|nostop|print|noprint|pass|nopass]...`
+    Handle(String, Vec<String>),
+    InfoThreads,
+    /// `thread N` switches to thread N; plain `thread` describes the current thread
+    Thread(Option<usize>),
+    /// `set follow-fork-mode parent|child`
+    SetFollowForkMode(FollowForkMode),
+    /// `source <file>`: run the commands in a file
+    Source(String),
+    /// `gcore [file]`: write a core file of the inferior
+    Gcore(Option<String>),
+    /// `commands [N]`, followed by lines up to `end`, to run whenever breakpoint N (or the last
+    /// breakpoint set) is hit
+    Commands(Option<usize>),
+    /// A command that was recognized but is missing something; the message says what
+    Malformed(String),
 }
 
 impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
+    pub fn from_tokens(tokens: &[&str]) -> Option<DebuggerCommand> {
         match tokens[0] {
             "q" | "quit" => Some(DebuggerCommand::Quit),
             "r" | "run" => {
@@ -18,9 +75,170 @@ impl DebuggerCommand {
             },
             "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
-            "b" | "break" | "breakpoint" => Some(DebuggerCommand::Breakpoint(tokens[1].to_string())),
+            "b" | "break" | "breakpoint" => {
+                let location = tokens.get(1)?.to_string();
+                let condition = match tokens.get(2) {
+                    Some(&"if") if tokens.len() > 3 => Some(tokens[3..].join(" ")),
+                    Some(&"if") => {
+                        return Some(DebuggerCommand::Malformed(
+                            "Argument required (boolean expression).\nUsage: {b | break | breakpoint} <location> [if <condition>]".to_string(),
+                        ))
+                    }
+                    Some(_) => return None,
+                    None => None,
+                };
+                Some(DebuggerCommand::Breakpoint(location, condition))
+            },
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "p" | "print" if tokens.len() > 1 => Some(DebuggerCommand::Print(tokens[1..].join(" "))),
+            "display" if tokens.len() > 1 => Some(DebuggerCommand::Display(Some(tokens[1..].join(" ")))),
+            "display" => Some(DebuggerCommand::Display(None)),
+            "undisplay" => Some(DebuggerCommand::Undisplay(parse_ids(&tokens[1..])?)),
+            "i" | "info" => match *tokens.get(1)? {
+                "locals" => Some(DebuggerCommand::InfoLocals),
+                "args" => Some(DebuggerCommand::InfoArgs),
+                "b" | "break" | "breakpoints" => Some(DebuggerCommand::InfoBreakpoints),
+                "threads" => Some(DebuggerCommand::InfoThreads),
+                "display" => Some(DebuggerCommand::InfoDisplay),
+                "shared" | "sharedlibrary" => Some(DebuggerCommand::InfoSharedLibrary),
+                "r" | "reg" | "registers" => Some(DebuggerCommand::InfoRegisters(
+                    tokens[2..].iter().map(|s| s.trim_start_matches('$').to_string()).collect(),
+                )),
+                _ => None,
+            },
+            "d" | "delete" => Some(DebuggerCommand::Delete(parse_ids(&tokens[1..])?)),
+            "disable" => Some(DebuggerCommand::Disable(parse_ids(&tokens[1..])?)),
+            "enable" => Some(DebuggerCommand::Enable(parse_ids(&tokens[1..])?)),
+            "ignore" => Some(DebuggerCommand::Ignore(
+                tokens.get(1)?.parse().ok()?,
+                tokens.get(2)?.parse().ok()?,
+            )),
+            "watch" if tokens.len() > 1 => Some(DebuggerCommand::Watch(tokens[1..].join(" "), WatchKind::Write)),
+            "rwatch" if tokens.len() > 1 => Some(DebuggerCommand::Watch(tokens[1..].join(" "), WatchKind::Read)),
+            "awatch" if tokens.len() > 1 => Some(DebuggerCommand::Watch(tokens[1..].join(" "), WatchKind::Access)),
+            "attach" => Some(DebuggerCommand::Attach(tokens.get(1)?.parse().ok()?)),
+            "detach" => Some(DebuggerCommand::Detach),
+            "up" => Some(DebuggerCommand::Up(parse_count(tokens.get(1))?)),
+            "down" => Some(DebuggerCommand::Down(parse_count(tokens.get(1))?)),
+            "f" | "frame" => match tokens.get(1) {
+                Some(level) => Some(DebuggerCommand::Frame(Some(level.parse().ok()?))),
+                None => Some(DebuggerCommand::Frame(None)),
+            },
+            "l" | "list" => Some(DebuggerCommand::List(tokens.get(1).map(|spec| spec.to_string()))),
+            "disas" | "disassemble" => {
+                Some(DebuggerCommand::Disassemble(tokens.get(1).map(|spec| spec.to_string())))
+            },
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens.get(1)?.to_string(),
+                tokens[2..].iter().map(|keyword| keyword.to_string()).collect(),
+            )),
+            "thread" => match tokens.get(1) {
+                Some(number) => Some(DebuggerCommand::Thread(Some(number.parse().ok()?))),
+                None => Some(DebuggerCommand::Thread(None)),
+            },
+            "gcore" | "generate-core-file" => {
+                Some(DebuggerCommand::Gcore(tokens.get(1).map(|file| file.to_string())))
+            },
+            "source" => Some(DebuggerCommand::Source(tokens.get(1)?.to_string())),
+            "commands" => match tokens.get(1) {
+                Some(id) => Some(DebuggerCommand::Commands(Some(id.parse().ok()?))),
+                None => Some(DebuggerCommand::Commands(None)),
+            },
+            "set" if tokens.get(1) == Some(&"follow-fork-mode") => match *tokens.get(2)? {
+                "parent" => Some(DebuggerCommand::SetFollowForkMode(FollowForkMode::Parent)),
+                "child" => Some(DebuggerCommand::SetFollowForkMode(FollowForkMode::Child)),
+                _ => None,
+            },
+            "set" => {
+                let assignment = match tokens.get(1) {
+                    Some(&"var") | Some(&"variable") => tokens[2..].join(" "),
+                    _ => tokens[1..].join(" "),
+                };
+                let (target, value) = split_assignment(&assignment)?;
+                Some(DebuggerCommand::Set(target, value))
+            },
+            cmd if (cmd == "x" || cmd.starts_with("x/")) && tokens.len() > 1 => {
+                let (count, format, size) = parse_examine_format(cmd.strip_prefix("x/").unwrap_or(""))?;
+                Some(DebuggerCommand::Examine(count, format, size, tokens[1..].join(" ")))
+            },
             // Default case:
             _ => None,
         }
     }
 }
+
+/// Splits `target = value` at its assignment operator, skipping over the `=`s of comparisons and
+/// any inside brackets or character literals. A compound assignment is spelled out, so `x += 1`
+/// becomes `x` and `x + (1)`.
+fn split_assignment(text: &str) -> Option<(String, String)> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut in_char = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_char => i += 1,
+            b'\'' => in_char = !in_char,
+            _ if in_char => {}
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'=' if depth == 0 && bytes.get(i + 1) == Some(&b'=') => i += 1,
+            b'=' if depth == 0 => {
+                let (target, value) = (&text[..i], text[i + 1..].trim());
+                // Longer operators first, so that `<<=` isn't taken for `<=`
+                let op = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "<", ">", "!"]
+                    .into_iter()
+                    .find(|op| target.ends_with(op));
+                match op {
+                    None => return Some((target.trim().to_string(), value.to_string())),
+                    // `<=`, `>=` and `!=` are comparisons, not assignments
+                    Some("<" | ">" | "!") => {}
+                    Some(op) => {
+                        let target = target[..target.len() - op.len()].trim();
+                        return Some((target.to_string(), format!("{} {} ({})", target, op, value)));
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Parses a list of breakpoint numbers. An empty list means "all breakpoints".
+fn parse_ids(tokens: &[&str]) -> Option<Vec<usize>> {
+    tokens.iter().map(|token| token.parse::<usize>().ok()).collect()
+}
+
+/// Parses the `<count><format><unit size>` part of `x/...`, e.g. `4xw` or `s`. Defaults to one
+/// hex word (or one byte for the char and string formats).
+fn parse_examine_format(spec: &str) -> Option<(usize, char, usize)> {
+    let digits_end = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+    let count = if digits_end == 0 { 1 } else { spec[..digits_end].parse().ok()? };
+    let mut format = 'x';
+    let mut size = None;
+    for c in spec[digits_end..].chars() {
+        match c {
+            'x' | 'd' | 'u' | 'o' | 't' | 'c' | 's' | 'i' => format = c,
+            'b' => size = Some(1),
+            'h' => size = Some(2),
+            'w' => size = Some(4),
+            'g' => size = Some(8),
+            _ => return None,
+        }
+    }
+    let default_size = if format == 'c' || format == 's' || format == 'i' { 1 } else { 4 };
+    Some((count, format, size.unwrap_or(default_size)))
+}
+
+/// Parses the optional count argument of `up`/`down`, which defaults to 1
+fn parse_count(token: Option<&&str>) -> Option<usize> {
+    match token {
+        Some(count) => count.parse().ok(),
+        None => Some(1),
+    }
+}
@@ -0,0 +1,50 @@
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Mnemonic};
+
+/// Longest possible x86-64 instruction, in bytes
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// A decoded instruction
+pub struct Instruction {
+    pub address: usize,
+    /// AT&T syntax, like objdump and gdb print it
+    pub text: String,
+    /// Destination of a direct jump or call
+    pub branch_target: Option<usize>,
+}
+
+/// Decodes instructions from `bytes`, which were read from memory at `address`. Stops after
+/// `max_count` instructions, or at the end of the buffer. Bytes that don't form a valid
+/// instruction decode as `(bad)`.
+pub fn decode(bytes: &[u8], address: usize, max_count: usize) -> Vec<Instruction> {
+    let mut decoder = Decoder::with_ip(64, bytes, address as u64, DecoderOptions::NONE);
+    let mut formatter = GasFormatter::new();
+    formatter.options_mut().set_first_operand_char_index(7);
+    formatter.options_mut().set_uppercase_hex(false);
+    formatter.options_mut().set_branch_leading_zeros(false);
+    let mut instructions = Vec::new();
+    while decoder.can_decode() && instructions.len() < max_count {
+        let instruction = decoder.decode();
+        let mut text = String::new();
+        if instruction.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+        }
+        instructions.push(Instruction {
+            address: instruction.ip() as usize,
+            text,
+            branch_target: match instruction.near_branch_target() {
+                0 => None,
+                target => Some(target as usize),
+            },
+        });
+    }
+    instructions
+}
+
+/// If `bytes` start with a call instruction, returns the length of that instruction (so that the
+/// call returns to its address plus the length)
+pub fn call_length(bytes: &[u8]) -> Option<usize> {
+    let instruction = Decoder::new(64, bytes, DecoderOptions::NONE).decode();
+    (instruction.mnemonic() == Mnemonic::Call).then_some(instruction.len())
+}
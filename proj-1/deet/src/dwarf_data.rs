@@ -1,23 +1,63 @@
 use crate::gimli_wrapper;
+use crate::unwind::{CallFrameInfo, Registers};
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{fmt, fs};
 
+/// Hands out the `TypeId::module` of each module loaded
+static NEXT_MODULE_ID: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug)]
 pub enum Error {
     ErrorOpeningFile,
     DwarfFormatError(gimli_wrapper::Error),
 }
 
+/// Debugging information for the executable and the shared libraries loaded alongside it. The
+/// executable always comes first.
 pub struct DwarfData {
+    modules: Vec<Module>,
+}
+
+/// The debugging information of one object file. Position-independent code (PIE executables and
+/// shared libraries) runs at `bias` bytes past the addresses recorded in the file; the addresses in
+/// `files` have already been adjusted, while lookups in `addr2line`, `cfi` and `symbols` have to
+/// subtract it.
+struct Module {
+    /// Distinguishes this module's types from those of other modules
+    id: usize,
+    path: String,
+    bias: usize,
+    /// Range of file addresses covered by the loadable segments
+    start: usize,
+    end: usize,
+    /// Range of file addresses of .text
+    text: (usize, usize),
+    entry: usize,
+    /// The dynamic linker requested in PT_INTERP
+    interpreter: Option<String>,
     files: Vec<File>,
+    /// Every type described in the DWARF, by the offset of its DIE
+    types: HashMap<usize, Type>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    cfi: CallFrameInfo,
+    /// Symbols from .symtab and .dynsym, sorted by address, for code without DWARF
+    symbols: Vec<Symbol>,
+}
+
+struct Symbol {
+    name: String,
+    address: usize,
+    size: usize,
+    is_function: bool,
 }
 
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DwarfData {{files: {:?}}}", self.files)
+        write!(f, "DwarfData {{files: {:?}}}", self.modules[0].files)
     }
 }
 
@@ -27,71 +67,279 @@ impl From<gimli_wrapper::Error> for Error {
     }
 }
 
-impl DwarfData {
-    pub fn from_file(path: &str) -> Result<DwarfData, Error> {
+impl Module {
+    fn load(path: &str) -> Result<Module, Error> {
         let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
         let mmap = unsafe { memmap2::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
         let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+            .map_err(|e| gimli_wrapper::Error::ObjectError(e.to_string()))?;
         let endian = if object.is_little_endian() {
             gimli::RunTimeEndian::Little
         } else {
             gimli::RunTimeEndian::Big
         };
+        let segments: Vec<(usize, usize)> = object
+            .segments()
+            .map(|segment| (segment.address() as usize, (segment.address() + segment.size()) as usize))
+            .collect();
+        let text = object
+            .section_by_name(".text")
+            .map_or((0, 0), |section| {
+                (section.address() as usize, (section.address() + section.size()) as usize)
+            });
+        let interpreter = object
+            .section_by_name(".interp")
+            .and_then(|section| section.data().ok())
+            .map(|data| String::from_utf8_lossy(data).trim_end_matches('\0').to_string());
+        let mut symbols: Vec<Symbol> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|symbol| symbol.is_definition() && symbol.address() != 0)
+            .filter_map(|symbol| {
+                Some(Symbol {
+                    name: demangle(symbol.name().ok()?),
+                    address: symbol.address() as usize,
+                    size: symbol.size() as usize,
+                    is_function: symbol.kind() == SymbolKind::Text,
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|symbol| symbol.address);
+        let id = NEXT_MODULE_ID.fetch_add(1, Ordering::Relaxed);
+        let (files, types) = gimli_wrapper::load_file(&object, endian, id)?;
+        Ok(Module {
+            id,
+            path: path.to_string(),
+            bias: 0,
+            start: segments.iter().map(|segment| segment.0).min().unwrap_or(0),
+            end: segments.iter().map(|segment| segment.1).max().unwrap_or(0),
+            text,
+            entry: object.entry() as usize,
+            interpreter,
+            files,
+            types,
+            addr2line: Context::new(&object).map_err(gimli_wrapper::Error::from)?,
+            cfi: CallFrameInfo::load(&object, endian),
+            symbols,
+        })
+    }
+
+    /// Moves the module to run at `bias` bytes past its file addresses
+    fn relocate(&mut self, bias: usize) {
+        let delta = bias.wrapping_sub(self.bias);
+        self.bias = bias;
+        self.cfi.set_bias(bias as u64);
+        let relocate_variable = |var: &mut Variable| {
+            for (start, end) in &mut var.scope {
+                *start = start.wrapping_add(delta);
+                *end = end.wrapping_add(delta);
+            }
+            match &mut var.location {
+                Location::Address(addr) => *addr = addr.wrapping_add(delta),
+                Location::Expression(expression) => expression.bias = bias,
+                Location::List(ranges) => {
+                    for (start, end, expression) in ranges {
+                        *start = start.wrapping_add(delta);
+                        *end = end.wrapping_add(delta);
+                        expression.bias = bias;
+                    }
+                }
+                Location::FramePointerOffset(_) | Location::Constant(_) => {}
+            }
+        };
+        for file in &mut self.files {
+            file.global_variables.iter_mut().for_each(relocate_variable);
+            for func in &mut file.functions {
+                func.address = func.address.wrapping_add(delta);
+                func.variables.iter_mut().for_each(relocate_variable);
+            }
+            for line in &mut file.lines {
+                line.address = line.address.wrapping_add(delta);
+            }
+        }
+    }
+
+    /// Returns true if `addr` (a runtime address) lies within one of the module's segments
+    fn contains(&self, addr: usize) -> bool {
+        let file_addr = addr.wrapping_sub(self.bias);
+        self.start <= file_addr && file_addr < self.end
+    }
+
+    /// Finds the function symbol covering `addr` (a runtime address). Of several aliases for the
+    /// same function (`puts`, `_IO_puts`), the one with the fewest leading underscores wins.
+    fn function_symbol(&self, addr: usize) -> Option<&Symbol> {
+        let file_addr = addr.wrapping_sub(self.bias);
+        let functions = self.symbols.iter().filter(|symbol| symbol.is_function);
+        let start = functions.clone().rev().find(|symbol| symbol.address <= file_addr)?.address;
+        functions
+            .filter(|symbol| symbol.address == start)
+            .filter(|symbol| symbol.size == 0 || file_addr < symbol.address + symbol.size)
+            .min_by_key(|symbol| symbol.name.len() - symbol.name.trim_start_matches('_').len())
+    }
+}
+
+impl DwarfData {
+    pub fn from_file(path: &str) -> Result<DwarfData, Error> {
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            modules: vec![Module::load(path)?],
         })
     }
 
-    #[allow(dead_code)]
+    /// Debugging information without any modules, as if for a stripped executable
+    #[cfg(test)]
+    pub fn empty() -> DwarfData {
+        DwarfData {
+            modules: Vec::new(),
+        }
+    }
+
+    /// Moves the executable to where it was actually loaded, given the runtime address of its
+    /// entry point (AT_ENTRY). Returns how far it moved (wrapping), which is 0 unless it is a PIE.
+    pub fn relocate_executable(&mut self, entry: usize) -> usize {
+        let executable = &mut self.modules[0];
+        let delta = entry.wrapping_sub(executable.entry + executable.bias);
+        executable.relocate(entry.wrapping_sub(executable.entry));
+        delta
+    }
+
+    /// Returns true if `addr` lies within the executable's segments
+    pub fn in_executable(&self, addr: usize) -> bool {
+        self.modules[0].contains(addr)
+    }
+
+    /// The dynamic linker the executable asks for, or None if it is statically linked
+    pub fn interpreter(&self) -> Option<&str> {
+        self.modules[0].interpreter.as_deref()
+    }
+
+    /// Loads the symbols of a shared library that was mapped `bias` bytes past its file
+    /// addresses. Returns false if it was already loaded.
+    pub fn load_library(&mut self, path: &str, bias: usize) -> Result<bool, Error> {
+        if self.modules[1..].iter().any(|module| module.path == path) {
+            return Ok(false);
+        }
+        let mut module = Module::load(path)?;
+        module.relocate(bias);
+        self.modules.push(module);
+        Ok(true)
+    }
+
+    /// Forgets the shared libraries for which `keep` returns false
+    pub fn retain_libraries(&mut self, keep: impl Fn(&str) -> bool) {
+        let mut first = true;
+        self.modules.retain(|module| std::mem::take(&mut first) || keep(&module.path));
+    }
+
+    /// Path, .text address range, and whether there is DWARF, for each loaded shared library
+    pub fn libraries(&self) -> Vec<(&str, usize, usize, bool)> {
+        self.modules[1..]
+            .iter()
+            .map(|module| {
+                (
+                    module.path.as_str(),
+                    module.text.0.wrapping_add(module.bias),
+                    module.text.1.wrapping_add(module.bias),
+                    !module.files.is_empty(),
+                )
+            })
+            .collect()
+    }
+
+    /// Looks up the runtime address of a symbol (of any kind) in the symbol tables
+    pub fn get_symbol_addr(&self, name: &str) -> Option<usize> {
+        self.modules.iter().find_map(|module| {
+            let symbol = module.symbols.iter().find(|symbol| symbol.name == name)?;
+            Some(symbol.address.wrapping_add(module.bias))
+        })
+    }
+
+    /// The module whose segments contain `addr`
+    fn module_for(&self, addr: usize) -> Option<&Module> {
+        self.modules.iter().find(|module| module.contains(addr))
+    }
+
+    /// Every compilation unit of every module
+    fn files(&self) -> impl Iterator<Item = &File> {
+        self.modules.iter().flat_map(|module| module.files.iter())
+    }
+
     fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
+        self.files().find(|f| {
             f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
         })
     }
 
-    #[allow(dead_code)]
+    /// Returns the full path of a source file, as recorded in the line number table. `file` may be
+    /// abbreviated as for breakpoints; None means the first compilation unit.
+    pub fn get_source_path(&self, file: Option<&str>) -> Option<String> {
+        let target_file = match file {
+            Some(filename) => self.get_target_file(filename)?,
+            None => self.modules[0].files.first()?,
+        };
+        match target_file.lines.first() {
+            Some(line) => Some(self.get_line_from_addr(line.address)?.file),
+            None => Some(target_file.name.clone()),
+        }
+    }
+
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self.modules[0].files.first()?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        // The first line at or after the requested one that has code, and its first instruction
+        let resolved = target_file
+            .lines
+            .iter()
+            .map(|line| line.number)
+            .filter(|number| *number >= line_number)
+            .min()?;
+        target_file
+            .lines
+            .iter()
+            .filter(|line| line.number == resolved)
+            .map(|line| line.address)
+            .min()
     }
 
-    #[allow(dead_code)]
+    /// Finds a function by name. A bare name like `describe` also finds `hello::describe`, though
+    /// an exact match is preferred.
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
-        match file {
-            Some(filename) => Some(
-                self.get_target_file(filename)?
-                    .functions
-                    .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
-            ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
-                    }
-                }
-                None
-            }
+        let suffix = format!("::{}", func_name);
+        let matches = |name: &str| name == func_name || name.ends_with(&suffix);
+        let functions: Vec<&Function> = match file {
+            Some(filename) => self.get_target_file(filename)?.functions.iter().collect(),
+            None => self.files().flat_map(|file| file.functions.iter()).collect(),
+        };
+        if let Some(func) = functions
+            .into_iter()
+            .filter(|func| matches(&func.name))
+            .min_by_key(|func| func.name != func_name)
+        {
+            return Some(func.address);
+        }
+        if file.is_some() {
+            return None;
         }
+        // Functions without debugging information, e.g. in a shared library
+        self.modules
+            .iter()
+            .flat_map(|module| {
+                module
+                    .symbols
+                    .iter()
+                    .filter(|symbol| symbol.is_function && matches(&symbol.name))
+                    .map(move |symbol| (symbol, module.bias))
+            })
+            .min_by_key(|(symbol, _)| symbol.name != func_name)
+            .map(|(symbol, bias)| symbol.address.wrapping_add(bias))
     }
 
-    #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
-        let location = self
+        let module = self.module_for(curr_addr)?;
+        let location = module
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(curr_addr.wrapping_sub(module.bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
@@ -100,20 +348,149 @@ impl DwarfData {
         })
     }
 
-    #[allow(dead_code)]
+    /// Returns the innermost function executing at `curr_addr`, which may have been inlined into
+    /// another
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
+        self.get_inline_chain(curr_addr).into_iter().next().map(|(name, _)| name)
+    }
+
+    /// Returns the functions executing at `curr_addr`, innermost first: any functions inlined
+    /// there, then the function the code belongs to. Each comes with its current line, which for
+    /// the callers of inlined functions is the line of the call.
+    pub fn get_inline_chain(&self, curr_addr: usize) -> Vec<(String, Option<Line>)> {
+        let module = match self.module_for(curr_addr) {
+            Some(module) => module,
+            None => return Vec::new(),
+        };
+        let mut chain = Vec::new();
+        if let Ok(mut frames) = module
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
-            .ok()?
-            .next()
-            .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+            .find_frames(curr_addr.wrapping_sub(module.bias).try_into().unwrap())
+        {
+            while let Ok(Some(frame)) = frames.next() {
+                let name = match frame.function.and_then(|function| function.raw_name().ok().map(|name| demangle(&name))) {
+                    Some(name) => name,
+                    None => break,
+                };
+                let line = frame.location.and_then(|location| {
+                    Some(Line {
+                        file: location.file?.to_string(),
+                        number: location.line?.try_into().unwrap(),
+                        address: curr_addr,
+                    })
+                });
+                chain.push((name, line));
+            }
+        }
+        if chain.is_empty() {
+            if let Some(symbol) = module.function_symbol(curr_addr) {
+                chain.push((symbol.name.clone(), None));
+            }
+        }
+        chain
+    }
+
+    /// Computes the CFA of the frame described by `regs` and the registers of its caller. See
+    /// CallFrameInfo::unwind.
+    pub fn unwind_frame(
+        &self,
+        regs: &Registers,
+        read_memory: &dyn Fn(u64) -> Option<u64>,
+    ) -> Option<(u64, Registers)> {
+        self.module_for(regs.pc()? as usize)?
+            .cfi
+            .unwind(regs, read_memory)
+    }
+
+    /// Looks up a type in the type graph
+    pub fn get_type(&self, id: TypeId) -> Option<&Type> {
+        self.modules
+            .iter()
+            .find(|module| module.id == id.module)?
+            .types
+            .get(&id.offset)
+    }
+
+    /// Finds a member of a struct, union or class: its offset within the value, and its type.
+    /// Members of anonymous structs and unions nested inside count too.
+    pub fn get_member(&self, ty: &Type, name: &str) -> Option<(usize, &Type)> {
+        let members = match &ty.kind {
+            TypeKind::Struct { members, .. } => members,
+            _ => return None,
+        };
+        if let Some(member) = members.iter().find(|member| member.name == name) {
+            return Some((member.offset, self.get_type(member.type_id)?));
+        }
+        members
+            .iter()
+            .filter(|member| member.name.is_empty())
+            .find_map(|member| {
+                let (offset, found) = self.get_member(self.get_type(member.type_id)?, name)?;
+                Some((member.offset + offset, found))
+            })
+    }
+
+    /// Finds a type by name, e.g. `struct point`, `u32` or `char *`. Complete definitions win over
+    /// declarations of the same name.
+    pub fn find_type(&self, name: &str) -> Option<&Type> {
+        self.modules
+            .iter()
+            .flat_map(|module| module.types.values())
+            .filter(|ty| ty.name == name)
+            .max_by_key(|ty| ty.size)
+    }
+
+    /// The type a pointer points to, or the element type of an array
+    pub fn get_target_type(&self, ty: &Type) -> Option<&Type> {
+        match ty.kind {
+            TypeKind::Pointer(Some(target)) => self.get_type(target),
+            TypeKind::Array { element, .. } => self.get_type(element),
+            _ => None,
+        }
+    }
+
+    /// Returns the function whose text contains `addr`, if any.
+    pub fn get_function_containing_addr(&self, addr: usize) -> Option<&Function> {
+        self.files()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.address <= addr && addr < func.address + func.text_length)
+    }
+
+    /// Returns true if `addr` is the first instruction of a row in the line number table, i.e. the
+    /// beginning of a source line.
+    pub fn is_line_boundary(&self, addr: usize) -> bool {
+        self.files()
+            .any(|file| file.lines.iter().any(|line| line.address == addr))
+    }
+
+    /// Returns the variables (parameters and locals) in scope at `addr`, innermost blocks first.
+    /// `inline_depth` picks the function they belong to when others have been inlined there: 0
+    /// for the function containing `addr`, 1 for the function inlined into it, and so on.
+    pub fn get_local_variables(&self, addr: usize, inline_depth: usize) -> Vec<&Variable> {
+        let mut vars: Vec<&Variable> = match self.get_function_containing_addr(addr) {
+            Some(func) => func
+                .variables
+                .iter()
+                .filter(|var| var.inline_depth == inline_depth)
+                .filter(|var| {
+                    var.scope.is_empty() || var.scope.iter().any(|(start, end)| *start <= addr && addr < *end)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        vars.sort_by_key(|var| std::cmp::Reverse(var.scope_depth));
+        vars
+    }
+
+    /// Looks up a global variable by name.
+    pub fn get_global_variable(&self, name: &str) -> Option<&Variable> {
+        self.files()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
     }
 
-    #[allow(dead_code)]
     pub fn print(&self) {
-        for file in &self.files {
+        for file in self.files() {
             println!("------");
             println!("{}", file.name);
             println!("------");
@@ -148,32 +525,197 @@ impl DwarfData {
     }
 }
 
+/// Turns a mangled Rust or C++ symbol name into the path you would write in source code
+/// (`hello::main`, `ns::Class::method`). Other names are returned unchanged.
+pub fn demangle(name: &str) -> String {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        // The alternate format leaves out the hash at the end
+        return format!("{:#}", demangled);
+    }
+    if name.starts_with("_Z") {
+        let options = cpp_demangle::DemangleOptions::new().no_params();
+        if let Some(demangled) = cpp_demangle::Symbol::new(name)
+            .ok()
+            .and_then(|symbol| symbol.demangle(&options).ok())
+        {
+            return demangled;
+        }
+    }
+    name.to_string()
+}
+
+/// Identifies a type by the module it was loaded from and the offset of its DIE in .debug_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeId {
+    pub module: usize,
+    pub offset: usize,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+    /// Declared in Rust code, so values are printed with Rust syntax
+    pub rust: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum TypeKind {
+    /// Integers, floating point numbers, characters and booleans, told apart by name
+    #[default]
+    Base,
+    /// Pointers and references, to the given type (None for `void *`)
+    Pointer(Option<TypeId>),
+    /// A fixed number of elements (unknown for `int a[]`)
+    Array {
+        element: TypeId,
+        count: Option<usize>,
+    },
+    /// Structs, unions and classes. Generic Rust types also list their type parameters (the `T`
+    /// of `Vec<T>`).
+    Struct {
+        members: Vec<Member>,
+        template_params: Vec<(String, TypeId)>,
+    },
+    /// A Rust enum, whose contents depend on the value of the `discriminant` member
+    Variants {
+        discriminant: Option<Member>,
+        variants: Vec<Variant>,
+    },
+    /// A C-style enumeration
+    Enum(Vec<(String, i64)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub offset: usize,
+    pub type_id: TypeId,
+}
+
+/// One variant of a Rust enum: `member` is present when the discriminant equals `value`, or, for
+/// the variant without a value, when it matches none of the others
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub value: Option<u64>,
+    pub member: Member,
 }
 
 impl Type {
     pub fn new(name: String, size: usize) -> Self {
         Type {
-            name: name,
-            size: size,
+            name,
+            size,
+            ..Default::default()
         }
     }
+
+    /// Pointers (and references) are printed and compared as addresses
+    pub fn is_pointer(&self) -> bool {
+        matches!(self.kind, TypeKind::Pointer(_)) || self.name.ends_with('*')
+    }
+
+    /// Formats the raw (little-endian) bytes of a value of this type the way you would write it
+    /// in C.
+    pub fn format_value(&self, bytes: &[u8]) -> String {
+        if bytes.is_empty() {
+            return "<unknown>".to_string();
+        }
+        let len = bytes.len().min(8);
+        let unsigned = raw_value(bytes);
+        let signed = self.value_as_i64(bytes);
+        let base_name = self.base_name();
+        if self.is_pointer() {
+            return format!("{:#x}", unsigned);
+        }
+        match base_name {
+            "float" | "f32" => f32::from_bits(unsigned as u32).to_string(),
+            "double" | "f64" => f64::from_bits(unsigned).to_string(),
+            "_Bool" | "bool" => (unsigned != 0).to_string(),
+            // Rust's char is a 4-byte Unicode scalar value
+            "char" if len == 4 => match char::from_u32(unsigned as u32) {
+                Some(c) => format!("{} '{}'", unsigned, c.escape_default()),
+                None => unsigned.to_string(),
+            },
+            name if name.contains("char") && len == 1 => {
+                format!("{} '{}'", signed, (unsigned as u8 as char).escape_default())
+            }
+            _ if self.is_unsigned() => unsigned.to_string(),
+            _ => signed.to_string(),
+        }
+    }
+
+    /// Interprets the raw bytes of a value of this (integer or pointer) type as an i64,
+    /// sign-extending signed types.
+    pub fn value_as_i64(&self, bytes: &[u8]) -> i64 {
+        let len = bytes.len().min(8);
+        let unsigned = raw_value(bytes);
+        if len == 0 || self.is_pointer() || self.is_unsigned() {
+            return unsigned as i64;
+        }
+        let shift = 64 - 8 * len;
+        ((unsigned << shift) as i64) >> shift
+    }
+
+    /// C's `unsigned ...` and Rust's `u8` to `u128` and `usize`
+    pub fn is_unsigned(&self) -> bool {
+        let name = self.base_name();
+        name.contains("unsigned")
+            || name.strip_prefix('u').is_some_and(|bits| {
+                bits == "size" || (!bits.is_empty() && bits.bytes().all(|b| b.is_ascii_digit()))
+            })
+    }
+
+    /// `float` and `double`, and Rust's `f32` and `f64`
+    pub fn is_float(&self) -> bool {
+        matches!(self.base_name(), "float" | "double" | "f32" | "f64")
+    }
+
+    /// The type name without const/volatile qualifiers
+    fn base_name(&self) -> &str {
+        self.name.trim_start_matches("const ").trim_start_matches("volatile ")
+    }
+}
+
+/// Zero-extends up to 8 little-endian bytes into a u64
+pub fn raw_value(bytes: &[u8]) -> u64 {
+    let len = bytes.len().min(8);
+    let mut raw = [0u8; 8];
+    raw[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(raw)
 }
 
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// Anything more involved (registers, DW_OP_piece...), evaluated when the variable is read
+    Expression(LocationExpression),
+    /// A location list: the expression that applies while the pc is in [start, end). Outside of
+    /// these ranges the variable has been optimized out, and an empty list means it always is.
+    List(Vec<(usize, usize, LocationExpression)>),
+    /// DW_AT_const_value: the variable has a known value but lives nowhere
+    Constant(Vec<u8>),
+}
+
+/// A DWARF location expression, as found in DW_AT_location or a location list entry
+#[derive(Debug, Clone)]
+pub struct LocationExpression {
+    pub bytes: Vec<u8>,
+    pub encoding: gimli::Encoding,
+    /// How far the module has been moved from its file addresses, which DW_OP_addr uses
+    pub bias: usize,
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Expression(expression) => write!(f, "Expression({:x?})", expression.bytes),
+            Location::List(ranges) => write!(f, "List({} ranges)", ranges.len()),
+            Location::Constant(bytes) => write!(f, "Constant({:x?})", bytes),
         }
     }
 }
@@ -191,6 +733,15 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
+    /// The pc ranges of the innermost lexical block (or inlined subroutine) the variable is
+    /// declared in; empty if it is visible throughout its function
+    pub scope: Vec<(usize, usize)>,
+    /// How many lexical blocks and inlined subroutines the declaration is nested in
+    pub scope_depth: usize,
+    /// How many of those are inlined subroutines: 0 for the function's own variables, 1 for those
+    /// of a function inlined into it, and so on
+    pub inline_depth: usize,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -199,6 +750,9 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    /// The register DW_AT_frame_base names (rustc uses %rsp), or None if it is the CFA (as with
+    /// gcc)
+    pub frame_base_register: Option<u16>,
     pub variables: Vec<Variable>,
 }
 
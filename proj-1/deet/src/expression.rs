@@ -0,0 +1,1258 @@
+//! The small C-like expression language shared by `print`, `display`, `set var`, `x`, `watch` and
+//! breakpoint conditions: arithmetic, comparisons, member access, indexing, dereference, casts
+//! and `$registers`, evaluated against inferior memory and the type graph loaded from DWARF.
+//!
+//! Expressions are parsed once into an `Expr` tree (breakpoint conditions keep theirs for every
+//! hit) and evaluated by an `Evaluator` for a particular frame. Values that live in memory remember
+//! their address, so that they can be assigned to, watched, or have their address taken.
+
+use crate::dwarf_data::{DwarfData, Location, Type, TypeKind, Variable};
+use crate::inferior::{Frame, Inferior};
+use crate::location::{self, Place};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    Complement,
+    Dereference,
+    AddressOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Multiply,
+    Divide,
+    Remainder,
+    Add,
+    Subtract,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Integer(i64),
+    Float(f64),
+    Char(char),
+    /// A variable, possibly with a Rust or C++ path (`hello::COUNTER`)
+    Variable(String),
+    /// `$rax`, without the `$`
+    Register(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `(type) operand`: the type as written without its `*`s, and how many `*`s there were
+    Cast(String, usize, Box<Expr>),
+    /// `operand.member`; `p->member` is parsed as `(*p).member`
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Integer(i64),
+    Float(f64),
+    Char(char),
+    Identifier(String),
+    Register(String),
+    Punct(&'static str),
+}
+
+/// Longer operators come first so that `<=` isn't taken for `<`
+const PUNCTUATION: [&str; 26] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!",
+    "~", "&", "|", "^", "(", ")", "[", "]", ".",
+];
+
+/// Binary operators from the loosest to the tightest binding; those on a row bind equally tightly
+const PRECEDENCE: [&[(&str, BinaryOp)]; 10] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual)],
+    &[
+        ("<", BinaryOp::Less),
+        ("<=", BinaryOp::LessEqual),
+        (">", BinaryOp::Greater),
+        (">=", BinaryOp::GreaterEqual),
+    ],
+    &[("<<", BinaryOp::ShiftLeft), (">>", BinaryOp::ShiftRight)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+    &[
+        ("*", BinaryOp::Multiply),
+        ("/", BinaryOp::Divide),
+        ("%", BinaryOp::Remainder),
+    ],
+];
+
+/// Words that make up the names of C's basic types
+const C_TYPE_WORDS: [&str; 12] = [
+    "const", "volatile", "signed", "unsigned", "char", "short", "int", "long", "float", "double",
+    "void", "_Bool",
+];
+
+const NOT_A_NUMBER: &str = "Argument to arithmetic operation not a number or boolean.";
+const OPTIMIZED_OUT: &str = "value has been optimized out";
+
+/// Parses an expression. Casts need to know which names are types, hence `debug_data`.
+pub fn parse(text: &str, debug_data: &DwarfData) -> Result<Expr, String> {
+    let mut parser = Parser {
+        text,
+        tokens: tokenize(text)?,
+        position: 0,
+        debug_data,
+    };
+    let expr = parser.binary(0)?;
+    if parser.position < parser.tokens.len() {
+        return Err(parser.error());
+    }
+    Ok(expr)
+}
+
+/// Splits an expression into tokens, each with the offset in `text` it starts at
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+    let bytes = text.as_bytes();
+    let is_word =
+        |i: usize| i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_');
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let token = if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        } else if c.is_ascii_digit() {
+            while is_word(i) || (i < bytes.len() && bytes[i] == b'.') {
+                i += 1;
+            }
+            parse_number(&text[start..i])
+                .ok_or_else(|| format!("Invalid number \"{}\".", &text[start..i]))?
+        } else if is_word(i) {
+            // `::` joins the parts of a path into one identifier
+            loop {
+                while is_word(i) {
+                    i += 1;
+                }
+                if text[i..].starts_with("::") && is_word(i + 2) {
+                    i += 2;
+                } else {
+                    break;
+                }
+            }
+            Token::Identifier(text[start..i].to_string())
+        } else if c == b'$' {
+            i += 1;
+            while is_word(i) {
+                i += 1;
+            }
+            Token::Register(text[start + 1..i].to_string())
+        } else if c == b'\'' {
+            let (value, len) = parse_char(&text[i + 1..]).ok_or("Unmatched single quote.")?;
+            i += len + 1;
+            Token::Char(value)
+        } else if let Some(punct) = PUNCTUATION
+            .iter()
+            .find(|punct| text[i..].starts_with(**punct))
+        {
+            i += punct.len();
+            Token::Punct(punct)
+        } else {
+            let c = text[i..].chars().next().unwrap();
+            return Err(format!("Invalid character '{}' in expression.", c));
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+/// Parses an integer (decimal, 0x hex or 0 octal, ignoring C's `u` and `l` suffixes) or a
+/// floating point number
+fn parse_number(literal: &str) -> Option<Token> {
+    let hex = literal.starts_with("0x") || literal.starts_with("0X");
+    if !hex && (literal.contains('.') || literal.contains(['e', 'E'])) {
+        return literal.parse().ok().map(Token::Float);
+    }
+    let digits = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    let value = if hex {
+        u64::from_str_radix(&digits[2..], 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(Token::Integer(value as i64))
+}
+
+/// Parses the rest of a character literal, after its opening quote. Returns the character and
+/// the length of the literal, including the closing quote.
+fn parse_char(text: &str) -> Option<(char, usize)> {
+    let mut chars = text.chars();
+    let value = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            'a' => '\x07',
+            'b' => '\x08',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            c => c,
+        },
+        c => c,
+    };
+    if chars.next()? != '\'' {
+        return None;
+    }
+    Some((value, text.len() - chars.as_str().len()))
+}
+
+/// A recursive descent parser over the tokens of one expression
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    debug_data: &'a DwarfData,
+}
+
+impl Parser<'_> {
+    /// Parses operators binding at least as tightly as those on row `level` of PRECEDENCE
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some((_, op)) = PRECEDENCE[level]
+            .iter()
+            .find(|(punct, _)| self.peek_punct(punct))
+        {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(*op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.tokens.get(self.position) {
+            Some((Token::Punct("-"), _)) => UnaryOp::Negate,
+            Some((Token::Punct("!"), _)) => UnaryOp::Not,
+            Some((Token::Punct("~"), _)) => UnaryOp::Complement,
+            Some((Token::Punct("*"), _)) => UnaryOp::Dereference,
+            Some((Token::Punct("&"), _)) => UnaryOp::AddressOf,
+            Some((Token::Punct("+"), _)) => {
+                self.position += 1;
+                return self.unary();
+            }
+            Some((Token::Punct("("), _)) => {
+                if let Some((name, pointers, end)) = self.cast_type() {
+                    self.position = end;
+                    return Ok(Expr::Cast(name, pointers, Box::new(self.unary()?)));
+                }
+                return self.postfix();
+            }
+            _ => return self.postfix(),
+        };
+        self.position += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.binary(0)?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(".") {
+                expr = Expr::Member(Box::new(expr), self.member_name()?);
+            } else if self.eat("->") {
+                let pointee = Expr::Unary(UnaryOp::Dereference, Box::new(expr));
+                expr = Expr::Member(Box::new(pointee), self.identifier()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let expr = match self.tokens.get(self.position) {
+            Some((Token::Integer(value), _)) => Expr::Integer(*value),
+            Some((Token::Float(value), _)) => Expr::Float(*value),
+            Some((Token::Char(value), _)) => Expr::Char(*value),
+            Some((Token::Identifier(name), _)) => Expr::Variable(name.clone()),
+            Some((Token::Register(name), _)) => Expr::Register(name.clone()),
+            Some((Token::Punct("("), _)) => {
+                self.position += 1;
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            _ => return Err(self.error()),
+        };
+        self.position += 1;
+        Ok(expr)
+    }
+
+    /// If the parenthesis at the current position starts a cast, returns the type it names
+    /// (without its `*`s), the number of `*`s, and the position just past the closing parenthesis
+    fn cast_type(&self) -> Option<(String, usize, usize)> {
+        let mut words = Vec::new();
+        let mut i = self.position + 1;
+        while let Some((Token::Identifier(word), _)) = self.tokens.get(i) {
+            words.push(word.as_str());
+            i += 1;
+        }
+        let mut pointers = 0;
+        while let Some((Token::Punct("*"), _)) = self.tokens.get(i) {
+            pointers += 1;
+            i += 1;
+        }
+        if words.is_empty() || !matches!(self.tokens.get(i), Some((Token::Punct(")"), _))) {
+            return None;
+        }
+        let name = words.join(" ");
+        let is_type = words.iter().all(|word| C_TYPE_WORDS.contains(word))
+            || (words.len() == 2 && matches!(words[0], "struct" | "union" | "enum"))
+            || self.debug_data.find_type(&name).is_some();
+        is_type.then_some((name, pointers, i + 1))
+    }
+
+    /// The name after a `.`: an identifier, or the number of a Rust tuple field
+    fn member_name(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.position) {
+            Some((Token::Integer(field), _)) => {
+                self.position += 1;
+                Ok(field.to_string())
+            }
+            _ => self.identifier(),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.position) {
+            Some((Token::Identifier(name), _)) => {
+                self.position += 1;
+                Ok(name.clone())
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn peek_punct(&self, punct: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some((Token::Punct(p), _)) if *p == punct)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.peek_punct(punct);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    /// gdb's complaint about the token at the current position
+    fn error(&self) -> String {
+        let near = match self.tokens.get(self.position) {
+            Some((_, start)) => &self.text[*start..],
+            None => "",
+        };
+        format!("A syntax error in expression, near `{}'.", near)
+    }
+}
+
+/// The result of evaluating an expression
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub ty: Type,
+    /// The raw (little-endian) bytes of the value
+    pub bytes: Vec<u8>,
+    /// Where the value lives in inferior memory, if it does
+    pub address: Option<usize>,
+    /// What the value points to, for pointers made up while evaluating (by `&` or a cast) rather
+    /// than described by DWARF
+    pointee: Option<Box<Type>>,
+    /// Set for variables whose value no longer exists at the current pc
+    pub optimized_out: bool,
+}
+
+impl Value {
+    fn new(ty: Type, bytes: Vec<u8>) -> Value {
+        Value {
+            ty,
+            bytes,
+            address: None,
+            pointee: None,
+            optimized_out: false,
+        }
+    }
+}
+
+/// How a value takes part in arithmetic
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    Integer,
+    Float,
+    Pointer,
+    /// Structs, unions, arrays and Rust enums
+    Aggregate,
+}
+
+fn class(ty: &Type) -> Class {
+    if ty.is_pointer() {
+        Class::Pointer
+    } else if ty.is_float() {
+        Class::Float
+    } else {
+        match ty.kind {
+            TypeKind::Base | TypeKind::Enum(_) => Class::Integer,
+            _ => Class::Aggregate,
+        }
+    }
+}
+
+/// Evaluates expressions in the context of one frame of the inferior
+pub struct Evaluator<'a> {
+    debug_data: &'a DwarfData,
+    inferior: &'a Inferior,
+    /// The frame whose locals are in scope; without one, only globals are
+    frame: Option<Frame>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(
+        debug_data: &'a DwarfData,
+        inferior: &'a Inferior,
+        frame: Option<Frame>,
+    ) -> Evaluator<'a> {
+        Evaluator {
+            debug_data,
+            inferior,
+            frame,
+        }
+    }
+
+    pub fn frame(&self) -> Option<&Frame> {
+        self.frame.as_ref()
+    }
+
+    pub fn evaluate(&self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Integer(value) => {
+                let name = if i32::try_from(*value).is_ok() {
+                    "int"
+                } else {
+                    "long"
+                };
+                Ok(integer(*value, named(name)))
+            }
+            Expr::Float(value) => Ok(Value::new(named("double"), value.to_le_bytes().to_vec())),
+            Expr::Char(value) => Ok(integer(*value as i64, named("char"))),
+            Expr::Variable(name) => self.variable(name),
+            Expr::Register(name) => self.register(name),
+            Expr::Unary(op, operand) => self.unary(*op, self.operand(operand)?),
+            // These two only evaluate their right-hand side if they need to
+            Expr::Binary(BinaryOp::And, left, right) => Ok(boolean(
+                self.is_true(&self.operand(left)?)? && self.is_true(&self.operand(right)?)?,
+            )),
+            Expr::Binary(BinaryOp::Or, left, right) => Ok(boolean(
+                self.is_true(&self.operand(left)?)? || self.is_true(&self.operand(right)?)?,
+            )),
+            Expr::Binary(op, left, right) => {
+                self.binary(*op, self.operand(left)?, self.operand(right)?)
+            }
+            Expr::Cast(name, pointers, operand) => {
+                let (ty, pointee) = self.cast_type(name, *pointers)?;
+                let bytes = self.convert(&self.operand(operand)?, &ty)?;
+                Ok(Value {
+                    pointee,
+                    ..Value::new(ty, bytes)
+                })
+            }
+            Expr::Member(operand, name) => self.member(self.operand(operand)?, name),
+            Expr::Index(operand, index) => {
+                let index = self.integer(&self.operand(index)?)?;
+                self.index(self.operand(operand)?, index)
+            }
+        }
+    }
+
+    /// Evaluates an operand of a larger expression, which can't do anything with a value that
+    /// has been optimized out
+    fn operand(&self, expr: &Expr) -> Result<Value, String> {
+        let value = self.evaluate(expr)?;
+        if value.optimized_out {
+            return Err(OPTIMIZED_OUT.to_string());
+        }
+        Ok(value)
+    }
+
+    /// Reads a variable of the frame (or a global)
+    pub fn variable_value(&self, var: &Variable) -> Result<Value, String> {
+        let read_memory = |addr, len| self.inferior.read_memory(addr, len).ok();
+        let ty = var.entity_type.clone();
+        match location::locate(var, self.frame.as_ref(), self.debug_data, &read_memory)? {
+            Place::Address(addr) => self.load(addr, ty),
+            Place::Value(bytes) => Ok(Value::new(ty, bytes)),
+            Place::OptimizedOut => Ok(Value {
+                optimized_out: true,
+                ..Value::new(ty, Vec::new())
+            }),
+        }
+    }
+
+    /// Whether a value counts as true in a condition: it is nonzero
+    pub fn is_true(&self, value: &Value) -> Result<bool, String> {
+        let value = self.decay(value.clone());
+        match class(&value.ty) {
+            Class::Float => Ok(self.float(&value)? != 0.0),
+            _ => Ok(self.integer(&value)? != 0),
+        }
+    }
+
+    /// The value of an integer, pointer or array (its address), truncating floating point numbers
+    pub fn integer(&self, value: &Value) -> Result<i64, String> {
+        if value.optimized_out {
+            return Err(OPTIMIZED_OUT.to_string());
+        }
+        let value = self.decay(value.clone());
+        match class(&value.ty) {
+            Class::Integer | Class::Pointer => Ok(value.ty.value_as_i64(&value.bytes)),
+            Class::Float => Ok(self.float(&value)? as i64),
+            Class::Aggregate => Err(NOT_A_NUMBER.to_string()),
+        }
+    }
+
+    /// Converts a value to type `ty`, as a cast or an assignment would, returning its bytes
+    pub fn convert(&self, value: &Value, ty: &Type) -> Result<Vec<u8>, String> {
+        if value.optimized_out {
+            return Err(OPTIMIZED_OUT.to_string());
+        }
+        let value = self.decay(value.clone());
+        match class(ty) {
+            Class::Float => {
+                let float = self.float(&value)?;
+                Ok(match ty.size {
+                    4 => (float as f32).to_le_bytes().to_vec(),
+                    _ => float.to_le_bytes().to_vec(),
+                })
+            }
+            Class::Integer | Class::Pointer => {
+                let mut integer = self.integer(&value)?;
+                if matches!(ty.name.as_str(), "_Bool" | "bool") {
+                    integer = (integer != 0) as i64;
+                }
+                Ok(truncate(integer, ty.size))
+            }
+            Class::Aggregate if value.ty.size == ty.size => Ok(value.bytes),
+            Class::Aggregate => Err("Invalid cast.".to_string()),
+        }
+    }
+
+    fn float(&self, value: &Value) -> Result<f64, String> {
+        if value.optimized_out {
+            return Err(OPTIMIZED_OUT.to_string());
+        }
+        match class(&value.ty) {
+            Class::Float if value.bytes.len() == 4 => {
+                Ok(f32::from_le_bytes(value.bytes[..].try_into().unwrap()) as f64)
+            }
+            Class::Float if value.bytes.len() == 8 => {
+                Ok(f64::from_le_bytes(value.bytes[..].try_into().unwrap()))
+            }
+            Class::Integer if value.ty.is_unsigned() => Ok(self.integer(value)? as u64 as f64),
+            Class::Integer | Class::Pointer => Ok(self.integer(value)? as f64),
+            _ => Err(NOT_A_NUMBER.to_string()),
+        }
+    }
+
+    /// Looks up a variable of the frame by name
+    fn local(&self, name: &str) -> Option<&'a Variable> {
+        let frame = self.frame.as_ref()?;
+        self.debug_data
+            .get_local_variables(frame.lookup_pc(), frame.inline_depth)
+            .into_iter()
+            .find(|var| var.name == name)
+    }
+
+    /// Returns true if `expr` refers to variables that live in the frame (rather than globals
+    /// or statics), so that what it designates goes away when the frame returns
+    pub fn uses_frame(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Variable(name) => self
+                .local(name)
+                .is_some_and(|var| !matches!(var.location, Location::Address(_))),
+            Expr::Unary(_, operand) | Expr::Cast(_, _, operand) | Expr::Member(operand, _) => {
+                self.uses_frame(operand)
+            }
+            Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+                self.uses_frame(left) || self.uses_frame(right)
+            }
+            Expr::Integer(_) | Expr::Float(_) | Expr::Char(_) | Expr::Register(_) => false,
+        }
+    }
+
+    fn variable(&self, name: &str) -> Result<Value, String> {
+        let local = self.local(name);
+        // Globals are known by their unqualified names, so `hello::COUNTER` is `COUNTER`
+        let unqualified = name.rsplit("::").next().unwrap();
+        let var = local
+            .or_else(|| self.debug_data.get_global_variable(name))
+            .or_else(|| self.debug_data.get_global_variable(unqualified))
+            .ok_or(format!("No symbol \"{}\" in current context.", name))?;
+        self.variable_value(var)
+    }
+
+    fn register(&self, name: &str) -> Result<Value, String> {
+        let value = self
+            .inferior
+            .get_register(name)
+            .map_err(|_| "Reading registers failed!".to_string())?
+            .ok_or(format!("Invalid register \"{}\"", name))?;
+        let ty = match name {
+            "rip" | "pc" | "rsp" | "sp" | "rbp" => Type {
+                kind: TypeKind::Pointer(None),
+                ..Type::new("void *".to_string(), 8)
+            },
+            _ => named("long"),
+        };
+        Ok(integer(value as i64, ty))
+    }
+
+    fn unary(&self, op: UnaryOp, value: Value) -> Result<Value, String> {
+        match op {
+            UnaryOp::Not => Ok(boolean(!self.is_true(&value)?)),
+            UnaryOp::Dereference => {
+                let value = self.decay(value);
+                let target = match class(&value.ty) {
+                    Class::Pointer => self.target_type(&value),
+                    _ => None,
+                }
+                .ok_or("Attempt to take contents of a non-pointer value.")?;
+                self.load(self.integer(&value)? as usize, target)
+            }
+            UnaryOp::AddressOf => {
+                let addr = value
+                    .address
+                    .ok_or("Attempt to take address of value not located in memory.")?;
+                Ok(pointer_to(value.ty, addr))
+            }
+            UnaryOp::Negate if class(&value.ty) == Class::Float => Ok(Value::new(
+                named("double"),
+                (-self.float(&value)?).to_le_bytes().to_vec(),
+            )),
+            UnaryOp::Negate | UnaryOp::Complement if class(&value.ty) == Class::Integer => {
+                let ty = promote(&value.ty, &value.ty);
+                let integer = self.integer(&value)?;
+                match op {
+                    UnaryOp::Negate => Ok(self::integer(integer.wrapping_neg(), ty)),
+                    _ => Ok(self::integer(!integer, ty)),
+                }
+            }
+            UnaryOp::Negate | UnaryOp::Complement => Err(NOT_A_NUMBER.to_string()),
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+        let (left, right) = (self.decay(left), self.decay(right));
+        let comparison = matches!(
+            op,
+            BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual
+        );
+        match (class(&left.ty), class(&right.ty)) {
+            (Class::Aggregate, _) | (_, Class::Aggregate) => Err(NOT_A_NUMBER.to_string()),
+            // Pointer arithmetic counts in elements
+            (Class::Pointer, Class::Pointer) if op == BinaryOp::Subtract => {
+                let difference = self.integer(&left)?.wrapping_sub(self.integer(&right)?);
+                Ok(integer(
+                    difference / self.element_size(&left),
+                    named("long"),
+                ))
+            }
+            (Class::Pointer, Class::Integer)
+                if matches!(op, BinaryOp::Add | BinaryOp::Subtract) =>
+            {
+                let mut offset = self.integer(&right)?.wrapping_mul(self.element_size(&left));
+                if op == BinaryOp::Subtract {
+                    offset = offset.wrapping_neg();
+                }
+                let addr = self.integer(&left)?.wrapping_add(offset);
+                Ok(Value {
+                    bytes: truncate(addr, left.ty.size),
+                    address: None,
+                    ..left
+                })
+            }
+            (Class::Integer, Class::Pointer) if op == BinaryOp::Add => self.binary(op, right, left),
+            (Class::Pointer, _) | (_, Class::Pointer) if !comparison => {
+                Err(NOT_A_NUMBER.to_string())
+            }
+            (Class::Float, _) | (_, Class::Float) => {
+                let (a, b) = (self.float(&left)?, self.float(&right)?);
+                let result = match op {
+                    BinaryOp::Multiply => a * b,
+                    BinaryOp::Divide => a / b,
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Subtract => a - b,
+                    BinaryOp::Less => return Ok(boolean(a < b)),
+                    BinaryOp::LessEqual => return Ok(boolean(a <= b)),
+                    BinaryOp::Greater => return Ok(boolean(a > b)),
+                    BinaryOp::GreaterEqual => return Ok(boolean(a >= b)),
+                    BinaryOp::Equal => return Ok(boolean(a == b)),
+                    BinaryOp::NotEqual => return Ok(boolean(a != b)),
+                    _ => return Err("Integer only operation.".to_string()),
+                };
+                Ok(Value::new(named("double"), result.to_le_bytes().to_vec()))
+            }
+            _ => {
+                let ty = if comparison && left.ty.is_pointer() {
+                    left.ty.clone()
+                } else {
+                    promote(&left.ty, &right.ty)
+                };
+                let unsigned = ty.is_unsigned() || ty.is_pointer();
+                let (a, b) = (self.integer(&left)?, self.integer(&right)?);
+                let ordering = if unsigned {
+                    (a as u64).cmp(&(b as u64))
+                } else {
+                    a.cmp(&b)
+                };
+                let result = match op {
+                    BinaryOp::Multiply => a.wrapping_mul(b),
+                    BinaryOp::Divide | BinaryOp::Remainder if b == 0 => {
+                        return Err("Division by zero".to_string())
+                    }
+                    BinaryOp::Divide if unsigned => ((a as u64) / (b as u64)) as i64,
+                    BinaryOp::Divide => a.wrapping_div(b),
+                    BinaryOp::Remainder if unsigned => ((a as u64) % (b as u64)) as i64,
+                    BinaryOp::Remainder => a.wrapping_rem(b),
+                    BinaryOp::Add => a.wrapping_add(b),
+                    BinaryOp::Subtract => a.wrapping_sub(b),
+                    BinaryOp::ShiftLeft => a.wrapping_shl(b as u32),
+                    BinaryOp::ShiftRight if unsigned => ((a as u64).wrapping_shr(b as u32)) as i64,
+                    BinaryOp::ShiftRight => a.wrapping_shr(b as u32),
+                    BinaryOp::BitAnd => a & b,
+                    BinaryOp::BitXor => a ^ b,
+                    BinaryOp::BitOr => a | b,
+                    BinaryOp::Less => return Ok(boolean(ordering.is_lt())),
+                    BinaryOp::LessEqual => return Ok(boolean(ordering.is_le())),
+                    BinaryOp::Greater => return Ok(boolean(ordering.is_gt())),
+                    BinaryOp::GreaterEqual => return Ok(boolean(ordering.is_ge())),
+                    BinaryOp::Equal => return Ok(boolean(a == b)),
+                    BinaryOp::NotEqual => return Ok(boolean(a != b)),
+                    BinaryOp::And => return Ok(boolean(a != 0 && b != 0)),
+                    BinaryOp::Or => return Ok(boolean(a != 0 || b != 0)),
+                };
+                Ok(integer(result, ty))
+            }
+        }
+    }
+
+    fn member(&self, value: Value, name: &str) -> Result<Value, String> {
+        if !matches!(value.ty.kind, TypeKind::Struct { .. }) {
+            return Err(
+                "Attempt to extract a component of a value that is not a structure.".to_string(),
+            );
+        }
+        // rustc calls the fields of tuple structs `__0`, `__1` and so on
+        let (offset, member_type) = self
+            .debug_data
+            .get_member(&value.ty, name)
+            .or_else(|| {
+                self.debug_data
+                    .get_member(&value.ty, &format!("__{}", name))
+            })
+            .ok_or(format!("There is no member named {}.", name))?;
+        let bytes = value
+            .bytes
+            .get(offset..offset + member_type.size)
+            .ok_or(format!("There is no member named {}.", name))?;
+        Ok(Value {
+            address: value.address.map(|addr| addr + offset),
+            ..Value::new(member_type.clone(), bytes.to_vec())
+        })
+    }
+
+    fn index(&self, value: Value, index: i64) -> Result<Value, String> {
+        let cannot_subscript = format!("cannot subscript something of type `{}'", value.ty.name);
+        // An array that isn't in memory, e.g. a member of a computed struct
+        if let (TypeKind::Array { count, .. }, None) = (&value.ty.kind, value.address) {
+            let element = self
+                .debug_data
+                .get_target_type(&value.ty)
+                .ok_or(cannot_subscript)?;
+            let count = count.unwrap_or(value.bytes.len() / element.size.max(1));
+            let bytes = element_range(index, count, element.size)
+                .and_then(|range| value.bytes.get(range))
+                .ok_or("no such vector element")?;
+            return Ok(Value::new(element.clone(), bytes.to_vec()));
+        }
+        let pointer = self.decay(value);
+        let target = match class(&pointer.ty) {
+            Class::Pointer => self.target_type(&pointer),
+            _ => None,
+        }
+        .ok_or(cannot_subscript)?;
+        let addr = self
+            .integer(&pointer)?
+            .wrapping_add(index.wrapping_mul(target.size as i64));
+        self.load(addr as usize, target)
+    }
+
+    /// Arrays in memory turn into a pointer to their first element, as in C
+    fn decay(&self, value: Value) -> Value {
+        match (&value.ty.kind, value.address) {
+            (TypeKind::Array { .. }, Some(addr)) => {
+                match self.debug_data.get_target_type(&value.ty) {
+                    Some(element) => pointer_to(element.clone(), addr),
+                    None => value,
+                }
+            }
+            _ => value,
+        }
+    }
+
+    fn target_type(&self, pointer: &Value) -> Option<Type> {
+        match &pointer.pointee {
+            Some(pointee) => Some(*pointee.clone()),
+            None => self.debug_data.get_target_type(&pointer.ty).cloned(),
+        }
+    }
+
+    /// The size of what a pointer points to, which is what pointer arithmetic counts in (1 for
+    /// `void *`, as gcc does)
+    fn element_size(&self, pointer: &Value) -> i64 {
+        match self.target_type(pointer) {
+            Some(target) if target.size > 0 => target.size as i64,
+            _ => 1,
+        }
+    }
+
+    /// Resolves the type of a cast, returning it and (for pointers) what it points to
+    fn cast_type(&self, name: &str, pointers: usize) -> Result<(Type, Option<Box<Type>>), String> {
+        let words: Vec<&str> = name.split(' ').collect();
+        let mut ty = if words.iter().all(|word| C_TYPE_WORDS.contains(word)) {
+            let (name, size) = c_type(&words);
+            self.debug_data
+                .find_type(name)
+                .cloned()
+                .unwrap_or_else(|| Type::new(name.to_string(), size))
+        } else {
+            self.debug_data
+                .find_type(name)
+                .cloned()
+                .ok_or(format!("No symbol \"{}\" in current context.", name))?
+        };
+        let mut pointee = None;
+        for _ in 0..pointers {
+            let name = match ty.name.ends_with('*') {
+                true => format!("{}*", ty.name),
+                false => format!("{} *", ty.name),
+            };
+            let pointer = match self.debug_data.find_type(&name) {
+                Some(pointer) => pointer.clone(),
+                None => Type {
+                    kind: TypeKind::Pointer(None),
+                    ..Type::new(name, 8)
+                },
+            };
+            pointee = Some(Box::new(ty));
+            ty = pointer;
+        }
+        Ok((ty, pointee))
+    }
+
+    fn load(&self, addr: usize, ty: Type) -> Result<Value, String> {
+        let bytes = self
+            .inferior
+            .read_memory(addr, ty.size)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+        Ok(Value {
+            address: Some(addr),
+            ..Value::new(ty, bytes)
+        })
+    }
+}
+
+/// A pointer to `target`, which lives at `addr`
+fn pointer_to(target: Type, addr: usize) -> Value {
+    let name = match target.rust {
+        true => format!("*mut {}", target.name),
+        false if target.name.ends_with('*') => format!("{}*", target.name),
+        false => format!("{} *", target.name),
+    };
+    let ty = Type {
+        kind: TypeKind::Pointer(None),
+        rust: target.rust,
+        ..Type::new(name, 8)
+    };
+    Value {
+        pointee: Some(Box::new(target)),
+        ..Value::new(ty, addr.to_le_bytes().to_vec())
+    }
+}
+
+/// The type of an integer operation on operands of types `left` and `right`: roughly C's usual
+/// arithmetic conversions, i.e. the wider of the two (but at least `int`), unsigned if either
+/// operand of that width is. Anything narrower than `int` becomes a (signed) `int` first.
+fn promote(left: &Type, right: &Type) -> Type {
+    let size = left.size.max(right.size).max(4);
+    let unsigned = [left, right]
+        .iter()
+        .any(|ty| ty.size == size && ty.is_unsigned());
+    match (size, unsigned) {
+        (0..=4, false) => named("int"),
+        (0..=4, true) => named("unsigned int"),
+        (_, false) => named("long"),
+        (_, true) => named("unsigned long"),
+    }
+}
+
+/// The gcc name and size of a C basic type spelled with `words`, e.g. `long unsigned int` for
+/// `unsigned long`
+fn c_type(words: &[&str]) -> (&'static str, usize) {
+    let unsigned = words.contains(&"unsigned");
+    let longs = words.iter().filter(|word| **word == "long").count();
+    if words.contains(&"char") {
+        return match (unsigned, words.contains(&"signed")) {
+            (true, _) => ("unsigned char", 1),
+            (false, true) => ("signed char", 1),
+            _ => ("char", 1),
+        };
+    }
+    match (unsigned, longs) {
+        _ if words.contains(&"float") => ("float", 4),
+        _ if words.contains(&"double") && longs > 0 => ("long double", 16),
+        _ if words.contains(&"double") => ("double", 8),
+        _ if words.contains(&"_Bool") => ("_Bool", 1),
+        _ if words.contains(&"void") => ("void", 1),
+        (false, _) if words.contains(&"short") => ("short int", 2),
+        (true, _) if words.contains(&"short") => ("short unsigned int", 2),
+        (false, 0) => ("int", 4),
+        (true, 0) => ("unsigned int", 4),
+        (false, 1) => ("long int", 8),
+        (true, 1) => ("long unsigned int", 8),
+        (false, _) => ("long long int", 8),
+        (true, _) => ("long long unsigned int", 8),
+    }
+}
+
+/// A basic type made up while evaluating, e.g. the `int` a comparison results in
+fn named(name: &str) -> Type {
+    let size = match name {
+        "char" => 1,
+        "int" | "unsigned int" => 4,
+        _ => 8,
+    };
+    Type::new(name.to_string(), size)
+}
+
+fn integer(value: i64, ty: Type) -> Value {
+    let bytes = truncate(value, ty.size);
+    Value::new(ty, bytes)
+}
+
+/// Comparisons and logical operators result in an `int` that is 0 or 1, as in C
+fn boolean(value: bool) -> Value {
+    integer(value as i64, named("int"))
+}
+
+/// Where element `index` lies in the bytes of an array of `count` elements of `size` bytes, or
+/// None if it is out of bounds
+fn element_range(index: i64, count: usize, size: usize) -> Option<Range<usize>> {
+    let index = usize::try_from(index).ok().filter(|index| *index < count)?;
+    let start = index.checked_mul(size)?;
+    Some(start..start.checked_add(size)?)
+}
+
+/// The low `size` bytes of a little-endian integer
+fn truncate(value: i64, size: usize) -> Vec<u8> {
+    let mut bytes = value.to_le_bytes().to_vec();
+    bytes.resize(size, if value < 0 { 0xff } else { 0 });
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates an expression that doesn't involve any variables, memory or registers
+    fn evaluate(text: &str) -> Result<Value, String> {
+        let debug_data = DwarfData::empty();
+        let inferior = Inferior::without_process();
+        let expr = parse(text, &debug_data)?;
+        Evaluator::new(&debug_data, &inferior, None).evaluate(&expr)
+    }
+
+    /// The type and value of an integer expression
+    fn integer(text: &str) -> (String, i64) {
+        let value = evaluate(text).unwrap();
+        let integer = value.ty.value_as_i64(&value.bytes);
+        (value.ty.name, integer)
+    }
+
+    fn float(text: &str) -> f64 {
+        let value = evaluate(text).unwrap();
+        assert_eq!(value.ty.name, "double");
+        f64::from_le_bytes(value.bytes[..].try_into().unwrap())
+    }
+
+    fn int(value: i64) -> (String, i64) {
+        ("int".to_string(), value)
+    }
+
+    #[test]
+    fn tokenize_operators_and_literals() {
+        let tokens: Vec<Token> = tokenize("p->x <= 0x1fUL && s::t[' ']")
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Identifier("p".to_string()),
+                Token::Punct("->"),
+                Token::Identifier("x".to_string()),
+                Token::Punct("<="),
+                Token::Integer(31),
+                Token::Punct("&&"),
+                Token::Identifier("s::t".to_string()),
+                Token::Punct("["),
+                Token::Char(' '),
+                Token::Punct("]"),
+            ]
+        );
+        let starts: Vec<usize> = tokenize(" a +$rax")
+            .unwrap()
+            .into_iter()
+            .map(|(_, start)| start)
+            .collect();
+        assert_eq!(starts, [1, 3, 4]);
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(parse_number("42"), Some(Token::Integer(42)));
+        assert_eq!(parse_number("0x1F"), Some(Token::Integer(31)));
+        assert_eq!(parse_number("017"), Some(Token::Integer(15)));
+        assert_eq!(parse_number("0"), Some(Token::Integer(0)));
+        assert_eq!(parse_number("10ul"), Some(Token::Integer(10)));
+        assert_eq!(parse_number("0xffffffffffffffff"), Some(Token::Integer(-1)));
+        assert_eq!(parse_number("1.5"), Some(Token::Float(1.5)));
+        assert_eq!(parse_number("1e3"), Some(Token::Float(1000.0)));
+        assert_eq!(parse_number("09"), None);
+        assert_eq!(parse_number("0xg"), None);
+        assert_eq!(parse_number("12abc"), None);
+        assert_eq!(parse_number("1.2.3"), None);
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(parse_char("a'"), Some(('a', 2)));
+        assert_eq!(parse_char("\\n' + 1"), Some(('\n', 3)));
+        assert_eq!(parse_char("\\0'"), Some(('\0', 3)));
+        assert_eq!(parse_char("\\''"), Some(('\'', 3)));
+        assert_eq!(parse_char("é'"), Some(('é', 3)));
+        assert_eq!(parse_char("ab'"), None);
+        assert_eq!(parse_char("a"), None);
+        assert_eq!(parse_char(""), None);
+        assert_eq!(integer("'\\t'"), ("char".to_string(), 9));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(integer("1 + 2 * 3"), int(7));
+        assert_eq!(integer("(1 + 2) * 3"), int(9));
+        assert_eq!(integer("10 - 4 - 3"), int(3));
+        assert_eq!(integer("100 / 10 / 5"), int(2));
+        assert_eq!(integer("1 << 2 + 1"), int(8));
+        assert_eq!(integer("1 | 2 ^ 3 & 1"), int(3));
+        assert_eq!(integer("1 < 2 == 1"), int(1));
+        assert_eq!(integer("-2 * 3"), int(-6));
+        assert_eq!(integer("!0 + 1"), int(2));
+        assert_eq!(integer("~0 + +1"), int(0));
+        assert_eq!(integer("0 || 1 && 0"), int(0));
+
+        let debug_data = DwarfData::empty();
+        let expr = parse("a || b && c", &debug_data).unwrap();
+        assert!(matches!(
+            expr,
+            Expr::Binary(BinaryOp::Or, left, right)
+                if matches!(*left, Expr::Variable(_))
+                    && matches!(*right, Expr::Binary(BinaryOp::And, ..))
+        ));
+        let expr = parse("-p->x[1]", &debug_data).unwrap();
+        assert!(matches!(
+            expr,
+            Expr::Unary(UnaryOp::Negate, operand) if matches!(*operand, Expr::Index(..))
+        ));
+        // A parenthesized variable is not a cast
+        let expr = parse("(x) - 1", &debug_data).unwrap();
+        assert!(matches!(expr, Expr::Binary(BinaryOp::Subtract, ..)));
+        let expr = parse("(unsigned long **) x", &debug_data).unwrap();
+        assert!(matches!(expr, Expr::Cast(name, 2, _) if name == "unsigned long"));
+    }
+
+    #[test]
+    fn casts_truncate() {
+        assert_eq!(integer("(char) 300"), ("char".to_string(), 44));
+        assert_eq!(integer("(char) 200"), ("char".to_string(), -56));
+        assert_eq!(
+            integer("(unsigned char) -1"),
+            ("unsigned char".to_string(), 255)
+        );
+        assert_eq!(integer("(short) 65537"), ("short int".to_string(), 1));
+        assert_eq!(
+            integer("(unsigned) -1"),
+            ("unsigned int".to_string(), 0xffffffff)
+        );
+        assert_eq!(integer("(long) -1"), ("long int".to_string(), -1));
+        assert_eq!(integer("(_Bool) 5"), ("_Bool".to_string(), 1));
+        assert_eq!(integer("(int) 3.9"), int(3));
+        assert_eq!(integer("(int) -3.9"), int(-3));
+        assert_eq!(float("(double) 7 / 2"), 3.5);
+        assert_eq!(float("(float) 0.1 + 0"), 0.1f32 as f64);
+        // Pointer arithmetic counts in elements of the type cast to
+        assert_eq!(integer("(char *) 0x10 + 1"), ("char *".to_string(), 0x11));
+        assert_eq!(integer("(int *) 0x10 + 1"), ("int *".to_string(), 0x14));
+        assert_eq!(
+            integer("(long *) 0x20 - (long *) 0x10"),
+            ("long".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn integer_promotion() {
+        assert_eq!(integer("'a' + 1"), int(98));
+        assert_eq!(
+            integer("(unsigned char) 200 + (unsigned char) 100"),
+            int(300)
+        );
+        assert_eq!(integer("(short) 1 * (char) 2"), int(2));
+        assert_eq!(integer("(unsigned char) 1 - 2"), int(-1));
+        assert_eq!(integer("-(unsigned short) 1"), int(-1));
+        assert_eq!(
+            integer("(unsigned) 1 - 2"),
+            ("unsigned int".to_string(), 0xffffffff)
+        );
+        assert_eq!(integer("-1 < (unsigned) 0"), int(0));
+        assert_eq!(integer("(unsigned) 0 - 1 > 0"), int(1));
+        assert_eq!(
+            integer("(unsigned) -8 >> 1"),
+            ("unsigned int".to_string(), 0x7ffffffc)
+        );
+        assert_eq!(integer("-8 >> 1"), int(-4));
+        assert_eq!(integer("-7 / 2"), int(-3));
+        assert_eq!(integer("-7 % 2"), int(-1));
+        assert_eq!(integer("2147483647 + 1"), int(-2147483648));
+        assert_eq!(integer("2147483648"), ("long".to_string(), 2147483648));
+        assert_eq!(integer("1 + (long) 1"), ("long".to_string(), 2));
+        assert_eq!(
+            integer("(unsigned long) -1 / 2"),
+            ("unsigned long".to_string(), i64::MAX)
+        );
+        assert_eq!(
+            integer("(unsigned) 1 + (long) -2"),
+            ("long".to_string(), -1)
+        );
+        assert_eq!(float("1 + 0.5"), 1.5);
+        assert_eq!(integer("1.5 < 2"), int(1));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(evaluate("1 / 0").unwrap_err(), "Division by zero");
+        assert_eq!(evaluate("1 % (2 - 2)").unwrap_err(), "Division by zero");
+        assert_eq!(
+            evaluate("(unsigned) 1 / 0").unwrap_err(),
+            "Division by zero"
+        );
+        assert_eq!(float("1.0 / 0"), f64::INFINITY);
+        assert_eq!(evaluate("1.0 % 2").unwrap_err(), "Integer only operation.");
+        // The smallest long divided by -1 overflows rather than trapping
+        assert_eq!(
+            integer("(long) -9223372036854775808 / -1"),
+            ("long".to_string(), i64::MIN)
+        );
+    }
+
+    #[test]
+    fn out_of_range_subscripts() {
+        assert_eq!(element_range(0, 4, 4), Some(0..4));
+        assert_eq!(element_range(3, 4, 4), Some(12..16));
+        assert_eq!(element_range(4, 4, 4), None);
+        assert_eq!(element_range(-1, 4, 4), None);
+        assert_eq!(element_range(i64::MIN, 4, 4), None);
+        assert_eq!(element_range(i64::MAX, usize::MAX, 8), None);
+        assert_eq!(element_range(0, 0, 1), None);
+        assert_eq!(
+            evaluate("5[1]").unwrap_err(),
+            "cannot subscript something of type `int'"
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        let debug_data = DwarfData::empty();
+        let error = |text| parse(text, &debug_data).unwrap_err();
+        assert_eq!(error("1 +"), "A syntax error in expression, near `'.");
+        assert_eq!(error("(1 + 2"), "A syntax error in expression, near `'.");
+        assert_eq!(error("1 + )"), "A syntax error in expression, near `)'.");
+        assert_eq!(error("1 2"), "A syntax error in expression, near `2'.");
+        assert_eq!(error("a[1"), "A syntax error in expression, near `'.");
+        assert_eq!(error("a.+"), "A syntax error in expression, near `+'.");
+        assert_eq!(error(""), "A syntax error in expression, near `'.");
+        assert_eq!(error("1 @ 2"), "Invalid character '@' in expression.");
+        assert_eq!(error("'a"), "Unmatched single quote.");
+        assert_eq!(error("12abc + 1"), "Invalid number \"12abc\".");
+        assert_eq!(
+            evaluate("nosuch + 1").unwrap_err(),
+            "No symbol \"nosuch\" in current context."
+        );
+        assert_eq!(
+            evaluate("(struct nosuch) 1").unwrap_err(),
+            "No symbol \"struct nosuch\" in current context."
+        );
+        assert_eq!(
+            evaluate("*1").unwrap_err(),
+            "Attempt to take contents of a non-pointer value."
+        );
+        assert_eq!(
+            evaluate("&1").unwrap_err(),
+            "Attempt to take address of value not located in memory."
+        );
+        assert_eq!(
+            evaluate("(1).x").unwrap_err(),
+            "Attempt to extract a component of a value that is not a structure."
+        );
+    }
+}
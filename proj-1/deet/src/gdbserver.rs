@@ -0,0 +1,519 @@
+//! A stub for the GDB remote serial protocol, so that gdb (or lldb, or an IDE) can control an
+//! inferior through deet: `deet --gdbserver :1234 <target>`, then `target remote :1234` in gdb.
+//!
+//! Packets look like `$<data>#<checksum>`, where the checksum is the sum of the data bytes modulo
+//! 256 in hex, and each one is acknowledged with `+` (unless the client turns that off with
+//! QStartNoAckMode). Breakpoints are software breakpoints managed by the `Inferior`; stops at one
+//! are reported with `swbreak`, which tells gdb that %rip already points at the breakpoint.
+//!
+//! Ctrl+C from gdb is not supported: a continued inferior only comes back when it stops by itself.
+
+use crate::inferior::{Inferior, Status};
+use crate::signals::SignalTable;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::collections::HashSet;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Largest packet we accept, as advertised to gdb in qSupported
+const PACKET_SIZE: usize = 0x4000;
+
+/// Registers in the order of gdb's `g` packet for x86-64, with their sizes in bytes. gdb accepts
+/// a packet that stops after the general purpose and segment registers and treats the floating
+/// point and vector registers as unavailable.
+const REGISTERS: [(&str, usize); 24] = [
+    ("rax", 8),
+    ("rbx", 8),
+    ("rcx", 8),
+    ("rdx", 8),
+    ("rsi", 8),
+    ("rdi", 8),
+    ("rbp", 8),
+    ("rsp", 8),
+    ("r8", 8),
+    ("r9", 8),
+    ("r10", 8),
+    ("r11", 8),
+    ("r12", 8),
+    ("r13", 8),
+    ("r14", 8),
+    ("r15", 8),
+    ("rip", 8),
+    ("eflags", 4),
+    ("cs", 4),
+    ("ss", 4),
+    ("ds", 4),
+    ("es", 4),
+    ("fs", 4),
+    ("gs", 4),
+];
+
+/// Runs `target` under ptrace and serves one gdb connection on `address` (`host:port`, or just
+/// `:port` to listen on every interface). Returns the exit code for deet.
+pub fn serve(address: &str, target: &str, args: &[String]) -> i32 {
+    let address = match address.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => address.to_string(),
+    };
+    let mut inferior = match Inferior::new(target, &args.to_vec(), &[], None) {
+        Some(inferior) => inferior,
+        None => {
+            eprintln!("Error starting subprocess");
+            return 1;
+        }
+    };
+    // gdb decides which signals to pass on (with `C`) and which to discard (with `c`)
+    inferior.set_signal_table(SignalTable::report_all());
+    println!("Process {} created; pid = {}", target, inferior.pid());
+
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Can't bind address {}: {}", address, err);
+            inferior.kill();
+            return 1;
+        }
+    };
+    if let Ok(local) = listener.local_addr() {
+        println!("Listening on port {}", local.port());
+    }
+    let stream = match listener.accept() {
+        Ok((stream, peer)) => {
+            println!("Remote debugging from host {}", peer.ip());
+            stream
+        }
+        Err(err) => {
+            eprintln!("Accepting a connection failed: {}", err);
+            inferior.kill();
+            return 1;
+        }
+    };
+    let mut server = GdbServer {
+        reader: BufReader::new(stream.try_clone().expect("Cloning the socket failed")),
+        writer: stream,
+        inferior: Some(inferior),
+        breakpoints: HashSet::new(),
+        ack: true,
+        last_stop: "S05".to_string(),
+    };
+    server.run();
+    0
+}
+
+struct GdbServer {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// None once the inferior has exited, been killed or been detached
+    inferior: Option<Inferior>,
+    /// Addresses of the breakpoints gdb inserted with Z0
+    breakpoints: HashSet<usize>,
+    /// Whether packets are still acknowledged (QStartNoAckMode turns this off)
+    ack: bool,
+    /// Reply to `?`: why the inferior last stopped
+    last_stop: String,
+}
+
+/// Whether to keep serving after a packet
+enum Next {
+    Continue,
+    /// The session is over (after `k` or `D`)
+    Done,
+}
+
+impl GdbServer {
+    fn run(&mut self) {
+        while let Some(packet) = self.read_packet() {
+            let (reply, next) = self.handle(&packet);
+            if let Some(reply) = reply {
+                if self.send(&reply).is_err() {
+                    break;
+                }
+            }
+            if let Next::Done = next {
+                break;
+            }
+        }
+        if let Some(mut inferior) = self.inferior.take() {
+            inferior.kill();
+        }
+    }
+
+    /// Reads the next packet, acknowledging it. Returns None once the connection is closed.
+    fn read_packet(&mut self) -> Option<String> {
+        loop {
+            // Skip acks and interrupt requests until the start of a packet
+            let mut byte = [0u8];
+            loop {
+                self.reader.read_exact(&mut byte).ok()?;
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                self.reader.read_exact(&mut byte).ok()?;
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut checksum = [0u8; 2];
+            self.reader.read_exact(&mut checksum).ok()?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok());
+            let valid = expected == Some(checksum_of(&data));
+            if self.ack {
+                self.writer.write_all(if valid { b"+" } else { b"-" }).ok()?;
+            }
+            if valid {
+                return Some(String::from_utf8_lossy(&unescape(&data)).to_string());
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.writer.write_all(packet.as_bytes())?;
+        if self.ack {
+            // Wait for the acknowledgement, resending if the client asks for it
+            let mut byte = [0u8];
+            loop {
+                self.reader.read_exact(&mut byte)?;
+                match byte[0] {
+                    b'+' => break,
+                    b'-' => self.writer.write_all(packet.as_bytes())?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Works out the reply to a packet, if it gets one. An empty reply tells gdb the packet isn't
+    /// supported.
+    fn handle(&mut self, packet: &str) -> (Option<String>, Next) {
+        let reply = match packet.chars().next() {
+            Some('q') => self.handle_query(packet),
+            Some('Q') if packet == "QStartNoAckMode" => {
+                // The OK itself is still acknowledged
+                let _ = self.send("OK");
+                self.ack = false;
+                return (None, Next::Continue);
+            }
+            Some('?') => self.last_stop.clone(),
+            // Kill gets no reply
+            Some('k') => return (None, Next::Done),
+            Some('D') => {
+                if let Some(mut inferior) = self.inferior.take() {
+                    if inferior.detach().is_err() {
+                        return (Some("E01".to_string()), Next::Continue);
+                    }
+                }
+                return (Some("OK".to_string()), Next::Done);
+            }
+            _ if self.inferior.is_none() => "E01".to_string(),
+            Some(command) => {
+                // Everything after the command letter; the letter matched is ASCII, so this only
+                // fails for packets that fall through to the unsupported reply anyway
+                let args = packet.get(1..).unwrap_or("");
+                self.handle_command(command, args)
+            }
+            None => String::new(),
+        };
+        (Some(reply), Next::Continue)
+    }
+
+    /// Handles the packets that need a live inferior. `args` is the packet without its command
+    /// letter.
+    fn handle_command(&mut self, command: char, args: &str) -> String {
+        match command {
+            'g' => self.read_registers(),
+            'G' => self.write_registers(args),
+            'p' => self.read_register(args),
+            'P' => self.write_register(args),
+            'm' => self.read_memory(args),
+            'M' => self.write_memory(args),
+            'c' => self.resume(args, None, false),
+            's' => self.resume(args, None, true),
+            'C' | 'S' => {
+                let (signal, addr) = args.split_once(';').unwrap_or((args, ""));
+                let signal = u8::from_str_radix(signal, 16)
+                    .ok()
+                    .and_then(|number| Signal::try_from(number as i32).ok());
+                self.resume(addr, signal, command == 'S')
+            }
+            'Z' | 'z' => self.set_breakpoint(args, command == 'Z'),
+            // Hg<tid>/Hc<tid>: skip the operation letter
+            'H' => match args.get(1..) {
+                Some(tid) => self.select_thread(tid),
+                None => "E01".to_string(),
+            },
+            'T' => match u32::from_str_radix(args, 16) {
+                Ok(tid) if self.has_thread(tid) => "OK".to_string(),
+                _ => "E01".to_string(),
+            },
+            _ => String::new(),
+        }
+    }
+
+    fn handle_query(&mut self, packet: &str) -> String {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => return String::new(),
+        };
+        if packet.starts_with("qSupported") {
+            format!("PacketSize={:x};QStartNoAckMode+;swbreak+", PACKET_SIZE)
+        } else if packet == "qAttached" {
+            if inferior.is_attached() { "1" } else { "0" }.to_string()
+        } else if packet == "qC" {
+            format!("QC{:x}", inferior.tid().as_raw())
+        } else if packet == "qfThreadInfo" {
+            let tids: Vec<String> = inferior
+                .threads()
+                .iter()
+                .map(|thread| format!("{:x}", thread.tid.as_raw()))
+                .collect();
+            format!("m{}", tids.join(","))
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    fn inferior(&mut self) -> &mut Inferior {
+        self.inferior.as_mut().unwrap()
+    }
+
+    fn has_thread(&mut self, tid: u32) -> bool {
+        self.inferior()
+            .threads()
+            .iter()
+            .any(|thread| thread.tid.as_raw() as u32 == tid)
+    }
+
+    /// `Hg<tid>`/`Hc<tid>`: pick the thread later packets apply to. 0 and -1 mean any thread.
+    fn select_thread(&mut self, tid: &str) -> String {
+        if tid == "0" || tid == "-1" {
+            return "OK".to_string();
+        }
+        let tid = match i32::from_str_radix(tid, 16) {
+            Ok(tid) => Pid::from_raw(tid),
+            Err(_) => return "E01".to_string(),
+        };
+        let inferior = self.inferior();
+        let number = inferior
+            .threads()
+            .iter()
+            .find(|thread| thread.tid == tid)
+            .map(|thread| thread.number);
+        match number {
+            Some(number) if inferior.select_thread(number) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_registers(&mut self) -> String {
+        let mut reply = String::new();
+        for (name, size) in REGISTERS {
+            match self.inferior().get_register(name) {
+                Ok(Some(value)) => reply += &to_hex(&value.to_le_bytes()[..size]),
+                _ => return "E01".to_string(),
+            }
+        }
+        reply
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        let bytes = match from_hex(data) {
+            Some(bytes) => bytes,
+            None => return "E01".to_string(),
+        };
+        let mut offset = 0;
+        for (name, size) in REGISTERS {
+            if offset + size > bytes.len() {
+                break;
+            }
+            let value = le_value(&bytes[offset..offset + size]);
+            if self.inferior().set_register(name, value).is_err() {
+                return "E01".to_string();
+            }
+            offset += size;
+        }
+        "OK".to_string()
+    }
+
+    /// `p<n>`: read register n
+    fn read_register(&mut self, number: &str) -> String {
+        let (name, size) = match usize::from_str_radix(number, 16).ok().and_then(|n| REGISTERS.get(n)) {
+            Some(register) => *register,
+            None => return "E01".to_string(),
+        };
+        match self.inferior().get_register(name) {
+            Ok(Some(value)) => to_hex(&value.to_le_bytes()[..size]),
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// `P<n>=<value>`: write register n
+    fn write_register(&mut self, args: &str) -> String {
+        let register = args.split_once('=').and_then(|(number, value)| {
+            let (name, size) = *REGISTERS.get(usize::from_str_radix(number, 16).ok()?)?;
+            let bytes = from_hex(value)?;
+            Some((name, le_value(&bytes[..size.min(bytes.len())])))
+        });
+        match register {
+            Some((name, value)) if self.inferior().set_register(name, value).is_ok() => {
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// `m<addr>,<len>`
+    fn read_memory(&mut self, args: &str) -> String {
+        let (addr, len) = match parse_addr_len(args) {
+            // The hex reply has to fit in the packet size we advertised
+            Some((addr, len)) if len <= PACKET_SIZE / 2 => (addr, len),
+            _ => return "E01".to_string(),
+        };
+        match self.inferior().read_memory(addr, len) {
+            Ok(bytes) => to_hex(&bytes),
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    /// `M<addr>,<len>:<bytes>`
+    fn write_memory(&mut self, args: &str) -> String {
+        let write = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_addr_len(range)?;
+            let bytes = from_hex(data)?;
+            (bytes.len() == len).then_some((addr, bytes))
+        });
+        match write {
+            Some((addr, bytes)) if self.inferior().write_memory(addr, &bytes).is_ok() => {
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// `Z0,<addr>,<kind>` / `z0,<addr>,<kind>`: insert or remove a software breakpoint
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',');
+        if fields.next() != Some("0") {
+            // Other kinds (hardware breakpoints, watchpoints) aren't supported
+            return String::new();
+        }
+        let addr = match fields.next().and_then(|addr| usize::from_str_radix(addr, 16).ok()) {
+            Some(addr) => addr,
+            None => return "E01".to_string(),
+        };
+        let ok = if insert {
+            self.breakpoints.insert(addr);
+            self.inferior().insert_breakpoint(addr).is_ok()
+        } else {
+            self.breakpoints.remove(&addr);
+            self.inferior().remove_breakpoint(addr).is_ok()
+        };
+        if ok { "OK" } else { "E01" }.to_string()
+    }
+
+    /// `c`/`s`/`C`/`S`: resume (optionally at `addr`, optionally delivering `signal`) and reply
+    /// once the inferior stops
+    fn resume(&mut self, addr: &str, signal: Option<Signal>, single_step: bool) -> String {
+        if !addr.is_empty() {
+            match u64::from_str_radix(addr, 16) {
+                Ok(addr) if self.inferior().set_register("rip", addr).is_ok() => {}
+                _ => return "E01".to_string(),
+            }
+        }
+        let inferior = self.inferior();
+        inferior.set_pending_signal(signal);
+        let result = if single_step {
+            inferior.step_instruction()
+        } else {
+            inferior.continue_execute(&mut |_, _| true)
+        };
+        let reply = match result {
+            Ok(Status::Stopped(signal, addr)) => {
+                let tid = inferior.tid().as_raw();
+                let swbreak = signal == Signal::SIGTRAP && self.breakpoints.contains(&addr);
+                format!(
+                    "T{:02x}thread:{:x};{}",
+                    signal as i32,
+                    tid,
+                    if swbreak { "swbreak:;" } else { "" }
+                )
+            }
+            Ok(Status::Execed(_)) => format!("T05thread:{:x};", inferior.tid().as_raw()),
+            Ok(Status::Exited(code)) => {
+                self.inferior = None;
+                format!("W{:02x}", code as u8)
+            }
+            Ok(Status::Signaled(signal)) => {
+                self.inferior = None;
+                format!("X{:02x}", signal as i32)
+            }
+            Err(_) => return "E01".to_string(),
+        };
+        self.last_stop = reply.clone();
+        reply
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Undoes the escaping of `#`, `$`, `}` and `*` in binary packet data: `}` followed by the byte
+/// XORed with 0x20
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => {
+                if let Some(escaped) = bytes.next() {
+                    result.push(escaped ^ 0x20);
+                }
+            }
+            _ => result.push(*byte),
+        }
+    }
+    result
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Interprets up to 8 little-endian bytes as a number
+fn le_value(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, byte| (value << 8) | *byte as u64)
+}
+
+/// Parses `<addr>,<len>` (both hex)
+fn parse_addr_len(text: &str) -> Option<(usize, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    // Reject ranges that run off the end of the address space
+    addr.checked_add(len)?;
+    Some((addr, len))
+}
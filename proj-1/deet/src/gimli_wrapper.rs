@@ -5,18 +5,26 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli;
 use gimli::{UnitOffset, UnitSectionOffset};
 use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    demangle, File, Function, Line, Location, LocationExpression, Member, Type, TypeId, TypeKind,
+    Variable, Variant,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+/// Loads the compilation units of an object file, and every type they describe. `module`
+/// distinguishes the TypeIds of this file's types from those of other files.
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+    module: usize,
+) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         match object.section_by_name(id.name()) {
@@ -37,13 +45,13 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     let borrow_section: &dyn for<'a> Fn(
         &'a borrow::Cow<[u8]>,
     ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+        &|section| gimli::EndianSlice::new(section, endian);
 
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    // Types may be used before they are defined (rustc does this a lot), so collect them all first
+    let offset_to_type = load_types(&dwarf, module)?;
 
     let mut compilation_units: Vec<File> = Vec::new();
 
@@ -54,9 +62,19 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
 
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        // Depth of the subprogram we are inside of, if any, and whether it was kept
+        let mut function_depth: Option<(isize, bool)> = None;
+        // Lexical blocks and inlined subroutines we are inside of, within the current function
+        let mut blocks: Vec<Block> = Vec::new();
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
+            if function_depth.is_some_and(|(function_depth, _)| depth <= function_depth) {
+                function_depth = None;
+            }
+            while blocks.last().is_some_and(|block| depth <= block.depth) {
+                blocks.pop();
+            }
             // Update the offset_to_type mapping for types
             // Update the variable list for formal params/variables
             match entry.tag() {
@@ -70,6 +88,11 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    // rustc appends the codegen unit: `src/main.rs/@/hello.<hash>-cgu.0`
+                    let name = match name.split_once("/@/") {
+                        Some((path, _)) => path.to_string(),
+                        None => name,
+                    };
                     compilation_units.push(File {
                         name,
                         global_variables: Vec::new(),
@@ -77,35 +100,18 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
+                    // Out-of-line copies of functions that are also inlined get their name from
+                    // the abstract instance
+                    if let Some(origin) = attr_unit_ref(entry, gimli::DW_AT_abstract_origin) {
+                        let origin = unit.entry(origin)?;
+                        func.name = attr_string(&origin, &unit, &dwarf, gimli::DW_AT_linkage_name)
+                            .map(|name| demangle(&name))
+                            .or_else(|| attr_string(&origin, &unit, &dwarf, gimli::DW_AT_name))
+                            .unwrap_or_default();
+                    }
+                    let mut has_code = false;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -113,9 +119,21 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         match attr.name() {
                             gimli::DW_AT_name => {
                                 if let Ok(DebugValue::Str(name)) = val {
-                                    func.name = name;
+                                    if func.name.is_empty() {
+                                        func.name = name;
+                                    }
+                                }
+                            }
+                            // The mangled name includes the path (`hello::main` rather than
+                            // `main`), so it takes precedence
+                            gimli::DW_AT_linkage_name | gimli::DW_AT_MIPS_linkage_name => {
+                                if let Ok(DebugValue::Str(name)) = val {
+                                    func.name = demangle(&name);
                                 }
                             }
+                            gimli::DW_AT_frame_base => {
+                                func.frame_base_register = get_frame_base_register(&attr, &unit);
+                            }
                             gimli::DW_AT_high_pc => {
                                 if let Ok(DebugValue::Uint(high_pc)) = val {
                                     func.text_length = high_pc.try_into().unwrap();
@@ -125,6 +143,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                 //println!("low pc {:?}", attr.value());
                                 if let Ok(DebugValue::Uint(low_pc)) = val {
                                     func.address = low_pc.try_into().unwrap();
+                                    has_code = true;
                                 }
                             }
                             gimli::DW_AT_decl_line => {
@@ -135,13 +154,47 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
+                    // Prototypes of functions defined elsewhere, and functions that only exist
+                    // inlined into others, have no code of their own
+                    let keep = has_code && !matches!(entry.attr(gimli::DW_AT_declaration), Ok(Some(_)));
+                    if keep {
+                        compilation_units.last_mut().unwrap().functions.push(func);
+                    }
+                    function_depth = Some((depth, keep));
+                }
+                gimli::DW_TAG_lexical_block | gimli::DW_TAG_inlined_subroutine
+                    if matches!(function_depth, Some((_, true))) =>
+                {
+                    let mut ranges = Vec::new();
+                    let mut iter = dwarf.die_ranges(&unit, entry)?;
+                    while let Some(range) = iter.next()? {
+                        ranges.push((range.begin as usize, range.end as usize));
+                    }
+                    // Blocks without code of their own cover the same code as their parent
+                    if ranges.is_empty() {
+                        ranges = blocks.last().map(|block| block.ranges.clone()).unwrap_or_default();
+                    }
+                    blocks.push(Block {
+                        depth,
+                        ranges,
+                        inlined: entry.tag() == gimli::DW_TAG_inlined_subroutine,
+                    });
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
                     let mut entity_type: Option<Type> = None;
                     let mut location: Option<Location> = None;
                     let mut line_number = 0;
+                    // Variables of inlined and out-of-line copies of a function describe only
+                    // their location, and refer to the abstract instance for the rest
+                    if let Some(origin) = attr_unit_ref(entry, gimli::DW_AT_abstract_origin) {
+                        let origin = unit.entry(origin)?;
+                        name = attr_string(&origin, &unit, &dwarf, gimli::DW_AT_name).unwrap_or_default();
+                        entity_type = attr_ref(&origin, &unit, gimli::DW_AT_type)
+                            .and_then(|offset| offset_to_type.get(&offset))
+                            .cloned();
+                        line_number = attr_udata(&origin, gimli::DW_AT_decl_line).unwrap_or(0);
+                    }
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -154,16 +207,19 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             }
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
+                                    if let Some(dtype) = offset_to_type.get(&offset) {
                                         entity_type = Some(dtype.clone());
                                     }
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
+                                if let Some(loc) = get_location(&attr, &unit, &dwarf) {
                                     location = Some(loc);
                                 }
                             }
+                            gimli::DW_AT_const_value => {
+                                location = get_const_value(&attr).map(Location::Constant);
+                            }
                             gimli::DW_AT_decl_line => {
                                 if let Ok(DebugValue::Uint(num)) = val {
                                     line_number = num;
@@ -172,28 +228,42 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
+                    // Locals without a location have been optimized out entirely. (Globals without
+                    // one are declarations of variables defined elsewhere.)
+                    if location.is_none()
+                        && matches!(function_depth, Some((_, true)))
+                        && !name.is_empty()
+                    {
+                        location = Some(Location::List(Vec::new()));
+                    }
+                    if let (Some(entity_type), Some(location)) = (entity_type, location) {
                         let var = Variable {
                             name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
+                            entity_type,
+                            location,
                             line_number: line_number.try_into().unwrap(),
+                            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+                            scope: blocks.last().map(|block| block.ranges.clone()).unwrap_or_default(),
+                            scope_depth: blocks.len(),
+                            inline_depth: blocks.iter().filter(|block| block.inlined).count(),
                         };
-                        if depth == 1 {
-                            compilation_units
+                        // Variables outside of any function are globals, even when nested in a
+                        // namespace
+                        match function_depth {
+                            None => compilation_units
                                 .last_mut()
                                 .unwrap()
                                 .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
+                                .push(var),
+                            Some((_, false)) => {}
+                            Some((_, true)) => compilation_units
                                 .last_mut()
                                 .unwrap()
                                 .functions
                                 .last_mut()
                                 .unwrap()
                                 .variables
-                                .push(var);
+                                .push(var),
                         }
                     }
                 }
@@ -203,7 +273,16 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
 
-        // Get line numbers
+        // Get line numbers. Paths in the line table may be relative to the compilation directory,
+        // and so may the unit's own name; compare them as absolute paths.
+        let comp_dir = match unit.comp_dir {
+            Some(ref dir) => path::PathBuf::from(dir.to_string_lossy().as_ref()),
+            None => path::PathBuf::new(),
+        };
+        let unit_path = compilation_units
+            .last()
+            .map(|file| comp_dir.join(&file.name))
+            .unwrap_or_default();
         if let Some(program) = unit.line_program.clone() {
             // Iterate over the line program rows.
             let mut rows = program.rows();
@@ -223,10 +302,12 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                    // Only keep rows for the unit's own source file (not e.g. inlined header code)
+                    let file = if comp_dir.join(&path) == unit_path {
+                        compilation_units.last_mut()
+                    } else {
+                        None
+                    };
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -247,19 +328,476 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
+}
+
+/// What a DIE enclosing the current one is, as far as collecting types is concerned
+enum Scope {
+    Namespace(String),
+    /// A struct, union, class or enumeration, by the offset of its DIE
+    Type(usize),
+    /// A Rust enum's variant part: the enum, and the member holding the discriminant
+    VariantPart(usize, Option<usize>),
+    /// One of a Rust enum's variants: the enum, and the discriminant value that selects it
+    Variant(usize, Option<u64>),
+    /// An array type, whose subranges give its dimensions
+    Array(usize),
+    Other,
+}
+
+/// A type defined in terms of another one (pointers, arrays, const, typedefs...). These get their
+/// names once every type has been seen.
+struct DerivedType {
+    tag: gimli::DwTag,
+    name: Option<String>,
+    target: Option<usize>,
+    size: Option<usize>,
+    rust: bool,
+    /// For arrays, the offset of each subrange DIE and its element count, if known
+    dimensions: Vec<(usize, Option<usize>)>,
+}
+
+/// Collects every type in the DWARF, by the offset of its DIE in .debug_info
+fn load_types<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    module: usize,
+) -> Result<HashMap<usize, Type>, Error> {
+    let mut types: HashMap<usize, Type> = HashMap::new();
+    let mut derived: HashMap<usize, DerivedType> = HashMap::new();
+    let type_id = |offset| TypeId { module, offset };
+
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut language = None;
+        let mut scopes: Vec<(isize, Scope)> = Vec::new();
+        let mut depth = 0;
+        let mut entries = unit.entries();
+        while let Some((delta_depth, entry)) = entries.next_dfs()? {
+            depth += delta_depth;
+            while scopes.last().is_some_and(|(scope_depth, _)| *scope_depth >= depth) {
+                scopes.pop();
+            }
+            let offset = match entry.offset().to_unit_section_offset(&unit) {
+                UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+                UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+            };
+            let rust = language == Some(gimli::DW_LANG_Rust);
+            let name = attr_string(entry, &unit, dwarf, gimli::DW_AT_name);
+            let size = attr_udata(entry, gimli::DW_AT_byte_size).map(|size| size as usize);
+            let parent = scopes.last().map(|(_, scope)| scope);
+            let mut scope = Scope::Other;
+            match entry.tag() {
+                gimli::DW_TAG_compile_unit => {
+                    if let Ok(Some(gimli::AttributeValue::Language(lang))) =
+                        entry.attr_value(gimli::DW_AT_language)
+                    {
+                        language = Some(lang);
+                    }
+                }
+                gimli::DW_TAG_namespace => {
+                    let name = name.unwrap_or_else(|| "(anonymous namespace)".to_string());
+                    scope = Scope::Namespace(name);
+                }
+                gimli::DW_TAG_base_type => {
+                    types.insert(
+                        offset,
+                        Type {
+                            name: name.unwrap_or_else(|| "<unknown>".to_string()),
+                            size: size.unwrap_or(0),
+                            kind: TypeKind::Base,
+                            rust,
+                        },
+                    );
+                }
+                gimli::DW_TAG_structure_type
+                | gimli::DW_TAG_union_type
+                | gimli::DW_TAG_class_type
+                | gimli::DW_TAG_enumeration_type => {
+                    let keyword = match entry.tag() {
+                        gimli::DW_TAG_union_type => "union",
+                        gimli::DW_TAG_class_type => "class",
+                        gimli::DW_TAG_enumeration_type => "enum",
+                        _ => "struct",
+                    };
+                    let c = !rust
+                        && !matches!(
+                            language,
+                            Some(gimli::DW_LANG_C_plus_plus)
+                                | Some(gimli::DW_LANG_C_plus_plus_03)
+                                | Some(gimli::DW_LANG_C_plus_plus_11)
+                                | Some(gimli::DW_LANG_C_plus_plus_14)
+                        );
+                    // C names need the keyword; Rust and C++ ones need their namespaces
+                    let name = match name {
+                        Some(name) if c => format!("{} {}", keyword, name),
+                        Some(name) => scopes
+                            .iter()
+                            .filter_map(|(_, scope)| match scope {
+                                Scope::Namespace(namespace) => Some(namespace.as_str()),
+                                _ => None,
+                            })
+                            .chain(std::iter::once(name.as_str()))
+                            .collect::<Vec<_>>()
+                            .join("::"),
+                        None => format!("{} {{...}}", keyword),
+                    };
+                    let kind = if entry.tag() == gimli::DW_TAG_enumeration_type {
+                        TypeKind::Enum(Vec::new())
+                    } else {
+                        TypeKind::Struct {
+                            members: Vec::new(),
+                            template_params: Vec::new(),
+                        }
+                    };
+                    types.insert(
+                        offset,
+                        Type {
+                            name,
+                            size: size.unwrap_or(0),
+                            kind,
+                            rust,
+                        },
+                    );
+                    scope = Scope::Type(offset);
+                }
+                gimli::DW_TAG_member => {
+                    let type_offset = match attr_ref(entry, &unit, gimli::DW_AT_type) {
+                        Some(type_offset) => type_offset,
+                        None => continue,
+                    };
+                    let member = Member {
+                        name: name.unwrap_or_default(),
+                        offset: get_member_offset(entry, &unit).unwrap_or(0),
+                        type_id: type_id(type_offset),
+                    };
+                    match parent {
+                        Some(Scope::Type(parent)) => {
+                            if let Some(Type {
+                                kind: TypeKind::Struct { members, .. },
+                                ..
+                            }) = types.get_mut(parent)
+                            {
+                                members.push(member);
+                            }
+                        }
+                        Some(Scope::VariantPart(parent, discr)) if *discr == Some(offset) => {
+                            if let Some(Type {
+                                kind: TypeKind::Variants { discriminant, .. },
+                                ..
+                            }) = types.get_mut(parent)
+                            {
+                                *discriminant = Some(member);
+                            }
+                        }
+                        Some(Scope::Variant(parent, value)) => {
+                            if let Some(Type {
+                                kind: TypeKind::Variants { variants, .. },
+                                ..
+                            }) = types.get_mut(parent)
+                            {
+                                variants.push(Variant {
+                                    value: *value,
+                                    member,
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                gimli::DW_TAG_variant_part => {
+                    if let Some(Scope::Type(parent)) = parent {
+                        if let Some(parent_type) = types.get_mut(parent) {
+                            parent_type.kind = TypeKind::Variants {
+                                discriminant: None,
+                                variants: Vec::new(),
+                            };
+                        }
+                        scope = Scope::VariantPart(
+                            *parent,
+                            attr_ref(entry, &unit, gimli::DW_AT_discr),
+                        );
+                    }
+                }
+                gimli::DW_TAG_variant => {
+                    if let Some(Scope::VariantPart(parent, _)) = parent {
+                        scope = Scope::Variant(*parent, attr_udata(entry, gimli::DW_AT_discr_value));
+                    }
+                }
+                gimli::DW_TAG_template_type_parameter => {
+                    if let (Some(Scope::Type(parent)), Some(name), Some(type_offset)) =
+                        (parent, name, attr_ref(entry, &unit, gimli::DW_AT_type))
+                    {
+                        if let Some(Type {
+                            kind: TypeKind::Struct { template_params, .. },
+                            ..
+                        }) = types.get_mut(parent)
+                        {
+                            template_params.push((name, type_id(type_offset)));
+                        }
+                    }
+                }
+                gimli::DW_TAG_enumerator => {
+                    let value = match entry.attr_value(gimli::DW_AT_const_value) {
+                        Ok(Some(gimli::AttributeValue::Sdata(value))) => Some(value),
+                        Ok(Some(value)) => value.udata_value().map(|value| value as i64),
+                        _ => None,
+                    };
+                    if let (Some(Scope::Type(parent)), Some(name), Some(value)) =
+                        (parent, name, value)
+                    {
+                        if let Some(Type {
+                            kind: TypeKind::Enum(enumerators),
+                            ..
+                        }) = types.get_mut(parent)
+                        {
+                            enumerators.push((name, value));
+                        }
+                    }
+                }
+                gimli::DW_TAG_pointer_type
+                | gimli::DW_TAG_reference_type
+                | gimli::DW_TAG_rvalue_reference_type
+                | gimli::DW_TAG_const_type
+                | gimli::DW_TAG_volatile_type
+                | gimli::DW_TAG_typedef
+                | gimli::DW_TAG_subroutine_type
+                | gimli::DW_TAG_array_type => {
+                    derived.insert(
+                        offset,
+                        DerivedType {
+                            tag: entry.tag(),
+                            name,
+                            target: attr_ref(entry, &unit, gimli::DW_AT_type),
+                            size,
+                            rust,
+                            dimensions: Vec::new(),
+                        },
+                    );
+                    if entry.tag() == gimli::DW_TAG_array_type {
+                        scope = Scope::Array(offset);
+                    }
+                }
+                gimli::DW_TAG_subrange_type => {
+                    let count = attr_udata(entry, gimli::DW_AT_count).or_else(|| {
+                        attr_udata(entry, gimli::DW_AT_upper_bound).map(|upper| upper + 1)
+                    });
+                    if let Some(Scope::Array(parent)) = parent {
+                        if let Some(array) = derived.get_mut(parent) {
+                            array.dimensions.push((offset, count.map(|count| count as usize)));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            scopes.push((depth, scope));
+        }
+    }
+
+    let offsets: Vec<usize> = derived.keys().copied().collect();
+    for offset in offsets {
+        resolve_derived_type(offset, &derived, &mut types, module, 0);
+    }
+    Ok(types)
+}
+
+/// Gives a derived type its name and layout, resolving the types it refers to first. Returns
+/// None if it refers to a type we know nothing about.
+fn resolve_derived_type(
+    offset: usize,
+    derived: &HashMap<usize, DerivedType>,
+    types: &mut HashMap<usize, Type>,
+    module: usize,
+    depth: usize,
+) -> Option<Type> {
+    if let Some(resolved) = types.get(&offset) {
+        return Some(resolved.clone());
+    }
+    // Chains of derived types are short; anything longer is a loop in broken DWARF
+    if depth > 32 {
+        return None;
+    }
+    let derived_type = derived.get(&offset)?;
+    let target = match derived_type.target {
+        Some(target) => Some(resolve_derived_type(target, derived, types, module, depth + 1)?),
+        None => None,
+    };
+    let target_name = target.as_ref().map_or("void", |target| target.name.as_str());
+    let resolved = match derived_type.tag {
+        gimli::DW_TAG_pointer_type
+        | gimli::DW_TAG_reference_type
+        | gimli::DW_TAG_rvalue_reference_type => {
+            let name = match (&derived_type.name, derived_type.tag) {
+                // Rust names its pointer types (`&str`, `*const u8`)
+                (Some(name), _) => name.clone(),
+                (None, gimli::DW_TAG_pointer_type) => match target_name.strip_suffix(" ()") {
+                    Some(return_type) => format!("{} (*)()", return_type),
+                    None if target_name.ends_with('*') => format!("{}*", target_name),
+                    None => format!("{} *", target_name),
+                },
+                (None, _) => format!("{} &", target_name),
+            };
+            Type {
+                name,
+                size: derived_type.size.unwrap_or(8),
+                kind: TypeKind::Pointer(derived_type.target.map(|offset| TypeId { module, offset })),
+                rust: derived_type.rust,
+            }
+        }
+        gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type => {
+            let qualifier = if derived_type.tag == gimli::DW_TAG_const_type {
+                "const"
+            } else {
+                "volatile"
+            };
+            let target = target.unwrap_or_else(|| Type::new("void".to_string(), 0));
+            Type {
+                name: format!("{} {}", qualifier, target.name),
+                ..target
+            }
+        }
+        gimli::DW_TAG_subroutine_type => Type::new(format!("{} ()", target_name), 0),
+        gimli::DW_TAG_array_type => {
+            // `int a[2][3]` is an array of 2 arrays of 3 ints. The inner arrays have no DIE of
+            // their own, so they are identified by their subrange DIEs.
+            let mut element = target?;
+            let mut element_id = TypeId {
+                module,
+                offset: derived_type.target?,
+            };
+            let dimensions = match derived_type.dimensions.as_slice() {
+                [] => vec![(offset, None)],
+                dimensions => dimensions.to_vec(),
+            };
+            for (i, (subrange, count)) in dimensions.iter().enumerate().rev() {
+                let array_offset = if i == 0 { offset } else { *subrange };
+                let array = Type {
+                    name: array_name(&element, *count),
+                    size: count.unwrap_or(0) * element.size,
+                    kind: TypeKind::Array {
+                        element: element_id,
+                        count: *count,
+                    },
+                    rust: derived_type.rust,
+                };
+                types.insert(array_offset, array.clone());
+                element = array;
+                element_id = TypeId {
+                    module,
+                    offset: array_offset,
+                };
+            }
+            element
+        }
+        // A typedef is the type it names, though it gives anonymous structs a name
+        _ => match (target?, &derived_type.name) {
+            (target, Some(name)) if target.name.ends_with("{...}") => Type {
+                name: name.clone(),
+                ..target
+            },
+            (target, _) => target,
+        },
+    };
+    types.insert(offset, resolved.clone());
+    Some(resolved)
+}
+
+/// Names an array type the way C (`int [2][3]`) or Rust (`[[i32; 3]; 2]`) would
+fn array_name(element: &Type, count: Option<usize>) -> String {
+    let count = count.map(|count| count.to_string()).unwrap_or_default();
+    if element.rust {
+        return format!("[{}; {}]", element.name, count);
+    }
+    // The dimensions of inner arrays go after ours
+    let (base, inner) = match element.name.find(" [") {
+        Some(pos) => element.name.split_at(pos),
+        None => (element.name.as_str(), ""),
+    };
+    let inner = inner.trim_start();
+    if base.ends_with('*') {
+        format!("{}[{}]{}", base, count, inner)
+    } else {
+        format!("{} [{}]{}", base, count, inner)
+    }
+}
+
+fn attr_string<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    name: gimli::DwAt,
+) -> Option<String> {
+    let value = entry.attr_value(name).ok()??;
+    let string = dwarf.attr_string(unit, value).ok()?;
+    let string = string.to_string_lossy().ok()?;
+    Some(string.into_owned())
+}
+
+fn attr_udata<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>, name: gimli::DwAt) -> Option<u64> {
+    entry.attr(name).ok()??.udata_value()
+}
+
+/// Returns the .debug_info offset of the DIE an attribute refers to
+fn attr_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    name: gimli::DwAt,
+) -> Option<usize> {
+    match entry.attr_value(name).ok()?? {
+        gimli::AttributeValue::UnitRef(offset) => match offset.to_unit_section_offset(unit) {
+            UnitSectionOffset::DebugInfoOffset(offset) => Some(offset.0),
+            UnitSectionOffset::DebugTypesOffset(offset) => Some(offset.0),
+        },
+        gimli::AttributeValue::DebugInfoRef(offset) => Some(offset.0),
+        _ => None,
+    }
+}
+
+/// Returns the offset within the unit of the DIE an attribute refers to, for looking it up with
+/// `Unit::entry`
+fn attr_unit_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Option<UnitOffset> {
+    match entry.attr_value(name).ok()?? {
+        gimli::AttributeValue::UnitRef(offset) => Some(offset),
+        _ => None,
+    }
+}
+
+/// DW_AT_data_member_location is usually a constant, but older DWARF uses an expression
+/// (DW_OP_plus_uconst)
+fn get_member_offset<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> Option<usize> {
+    let attr = entry.attr(gimli::DW_AT_data_member_location).ok()??;
+    if let Some(offset) = attr.udata_value() {
+        return Some(offset as usize);
+    }
+    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+        let mut pc = data.0.clone();
+        if let Ok(gimli::Operation::PlusConstant { value }) =
+            gimli::Operation::parse(&mut pc, unit.encoding())
+        {
+            return Some(value as usize);
+        }
+    }
+    None
 }
 
 #[derive(Debug, Clone)]
 pub enum DebugValue {
     Str(String),
     Uint(u64),
+    #[allow(dead_code)]
     Int(i64),
     Size(usize),
     NoVal,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     GimliError(gimli::Error),
     // Addr2lineError(addr2line::gimli::Error),
@@ -298,12 +836,26 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// A lexical block or inlined subroutine of the function being loaded
+struct Block {
+    /// Depth of its DIE in the tree
+    depth: isize,
+    ranges: Vec<(usize, usize)>,
+    inlined: bool,
+}
+
+/// Turns DW_AT_location into a Location. The simple cases unoptimized code uses (an offset from
+/// the frame base, or a fixed address) are recognized here; anything else is kept as an expression,
+/// or a location list of them, to be evaluated when the variable is read.
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
         let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
+        if let Ok(op) = gimli::Operation::parse(&mut pc, unit.encoding()) {
+            if pc.is_empty() {
                 match op {
                     gimli::Operation::FrameOffset { offset } => {
                         return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
@@ -315,6 +867,52 @@ fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) ->
                 }
             }
         }
+        return Some(Location::Expression(location_expression(data, unit)?));
+    }
+    let mut entries = dwarf.attr_locations(unit, attr.value()).ok()??;
+    let mut ranges = Vec::new();
+    while let Ok(Some(entry)) = entries.next() {
+        ranges.push((
+            entry.range.begin as usize,
+            entry.range.end as usize,
+            location_expression(&entry.data, unit)?,
+        ));
+    }
+    Some(Location::List(ranges))
+}
+
+fn location_expression<R: Reader>(
+    expression: &gimli::Expression<R>,
+    unit: &gimli::Unit<R>,
+) -> Option<LocationExpression> {
+    Some(LocationExpression {
+        bytes: expression.0.to_slice().ok()?.into_owned(),
+        encoding: unit.encoding(),
+        bias: 0,
+    })
+}
+
+/// The bytes of a DW_AT_const_value: a (little-endian) integer, or a block for anything bigger
+fn get_const_value<R: Reader>(attr: &gimli::Attribute<R>) -> Option<Vec<u8>> {
+    match attr.value() {
+        gimli::AttributeValue::Block(block) => Some(block.to_slice().ok()?.into_owned()),
+        gimli::AttributeValue::Sdata(value) => Some(value.to_le_bytes().to_vec()),
+        _ => Some(attr.udata_value()?.to_le_bytes().to_vec()),
+    }
+}
+
+/// Returns the register DW_AT_frame_base names, or None if it is something else (gcc uses the CFA)
+fn get_frame_base_register<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+) -> Option<u16> {
+    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+        let mut pc = data.0.clone();
+        if let Ok(gimli::Operation::Register { register }) =
+            gimli::Operation::parse(&mut pc, unit.encoding())
+        {
+            return Some(register.0);
+        }
     }
     None
 }
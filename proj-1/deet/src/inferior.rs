@@ -1,15 +1,24 @@
 // use libc::WNOWAIT;
+use nix::sys::personality::{self, Persona};
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::signal::Signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 use std::os::unix::process::CommandExt;
-use std::process::Child;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::mem::size_of;
-use crate::dwarf_data::DwarfData;
-use std::collections::HashMap;
+use crate::breakpoint::WatchKind;
+use crate::coredump::{self, CoreDump};
+use crate::disassembler;
+use crate::dwarf_data::{DwarfData, Line};
+use crate::location;
+use crate::signals::SignalTable;
+use crate::solib;
+use crate::unwind::Registers;
+use crate::value_format::ValueFormatter;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -22,20 +31,202 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior called exec. Contains the path of the new program. All breakpoints
+    /// were wiped out along with the old address space.
+    Execed(String),
 }
 
+/// Decides whether the inferior should really stop at the breakpoint at the given address (e.g.
+/// by evaluating breakpoint conditions). Returning false silently resumes the inferior.
+pub type StopFilter<'a> = dyn FnMut(&Inferior, usize) -> bool + 'a;
+
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
-    ptrace::traceme().or(Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "ptrace TRACEME failed",
-    )))
+    // Like gdb, turn off address space randomization so that shared libraries and PIE executables
+    // load at the same addresses every run, and breakpoints set in one run work in the next
+    if let Ok(persona) = personality::get() {
+        let _ = personality::set(persona | Persona::ADDR_NO_RANDOMIZE);
+    }
+    ptrace::traceme().or(Err(std::io::Error::other("ptrace TRACEME failed")))
+}
+
+/// A frame of the call stack, as recovered by unwinding
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// 0 for the innermost frame, counting up towards main. Inlined calls get frames of their own.
+    pub level: usize,
+    /// Which of the functions executing at pc this frame is for: 0 for the function the code
+    /// belongs to, 1 for a function inlined into it, and so on
+    pub inline_depth: usize,
+    /// Set when pc is a return address, i.e. for callers of functions that weren't inlined
+    pub is_caller: bool,
+    pub pc: usize,
+    /// Canonical frame address (which gcc also uses as the frame base), if there was CFI for pc
+    pub cfa: Option<usize>,
+    pub registers: Registers,
+}
+
+impl Frame {
+    /// The address to use for line and function lookups. Outer frames are stopped at a return
+    /// address, which may already belong to the next line (or even the next function).
+    pub fn lookup_pc(&self) -> usize {
+        if self.is_caller {
+            self.pc - 1
+        } else {
+            self.pc
+        }
+    }
+
+    /// The function this frame is executing and its current line. For the caller of an inlined
+    /// function that is the line of the call.
+    pub fn function(&self, debug_data: &DwarfData) -> (Option<String>, Option<Line>) {
+        let mut chain = debug_data.get_inline_chain(self.lookup_pc());
+        match chain.len().checked_sub(self.inline_depth + 1) {
+            Some(index) => {
+                let (name, line) = chain.swap_remove(index);
+                (Some(name), line)
+            }
+            None => (None, None),
+        }
+    }
+
+    /// Whether this frame called a function that was inlined into it, and so has no instruction
+    /// of its own to stop at
+    pub fn has_inlined_callee(&self, debug_data: &DwarfData) -> bool {
+        self.inline_depth + 1 < debug_data.get_inline_chain(self.lookup_pc()).len()
+    }
+
+    /// The address FramePointerOffset variable locations are relative to: the CFA, unless the
+    /// function's DW_AT_frame_base names a register instead
+    pub fn frame_base(&self, debug_data: &DwarfData) -> Option<usize> {
+        match debug_data
+            .get_function_containing_addr(self.lookup_pc())
+            .and_then(|func| func.frame_base_register)
+        {
+            Some(register) => self.registers.get(register).map(|value| value as usize),
+            None => self.cfa,
+        }
+    }
 }
 
+/// Which process to keep debugging when the inferior forks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FollowForkMode {
+    Parent,
+    Child,
+}
+
+/// A thread of the inferior
+#[derive(Debug, Clone, Copy)]
+pub struct Thread {
+    /// Number shown by `info threads` and used by `thread N`
+    pub number: usize,
+    pub tid: Pid,
+}
+
+/// Events we want to hear about: new threads, forks (and vfork children releasing the parent's
+/// memory), and execs
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+}
+
+/// Backtraces stop after this many frames, in case the stack is corrupted into a loop
+const MAX_FRAMES: usize = 1024;
+
+/// The inferior is debugged in all-stop mode: when one thread stops, every other thread is
+/// stopped too, and they are all resumed together (except when single-stepping, which only runs
+/// the current thread).
 pub struct Inferior {
-    child: Child,
+    /// Process id (which is also the tid of the main thread)
+    pid: Pid,
+    /// Thread that registers are read from and that single-steps
+    tid: Pid,
+    threads: Vec<Thread>,
+    next_thread_number: usize,
+    /// Threads that have been resumed and not yet reported a stop
+    running: HashSet<Pid>,
+    /// True if we attached to an already-running process rather than spawning it ourselves
+    attached: bool,
     breakpoints: HashMap<usize, u8>,
+    /// What to do with signals the inferior receives
+    signals: SignalTable,
+    /// Signals that stopped a thread and should be delivered when it resumes
+    pending_signals: HashMap<Pid, Signal>,
+    /// Threads with a SIGSTOP on the way that was sent by us and should be swallowed
+    pending_sigstops: HashSet<Pid>,
+    /// Threads that hit a breakpoint while being stopped by us, and were backed up so that they
+    /// hit it again (rather than stepping over it) when resumed
+    rewound: HashSet<Pid>,
+    follow_fork_mode: FollowForkMode,
+    /// Debug register slots programmed for rwatch, with the address and length they watch and
+    /// the contents last seen there. x86 also traps on writes to them, which are filtered out.
+    read_watchpoints: HashMap<usize, (usize, usize, Vec<u8>)>,
+    /// Set when this is a dead process loaded from a core file, whose registers and memory come
+    /// from the core rather than from ptrace
+    core: Option<CoreDump>,
+}
+
+/// Offset of debug register `index` (DR0-DR7) within `struct user`, for PTRACE_PEEKUSER/POKEUSER
+fn debug_register_offset(index: usize) -> ptrace::AddressType {
+    (std::mem::offset_of!(libc::user, u_debugreg) + index * size_of::<u64>()) as ptrace::AddressType
+}
+
+/// Registers in the order gdb's `info registers` prints them
+const REGISTER_NAMES: [&str; 24] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs",
+];
+
+/// Maps a register name to its field in user_regs_struct
+fn register_mut<'a>(regs: &'a mut libc::user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" => &mut regs.rbp,
+        "rsp" | "sp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" | "pc" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        "orig_rax" => &mut regs.orig_rax,
+        _ => return None,
+    })
+}
+
+/// Overwrites one byte of memory of a stopped thread (or process)
+fn poke_byte(pid: Pid, addr: usize, byte: u8) -> Result<(), nix::Error> {
+    let aligned_addr = align_addr_to_word(addr);
+    let byte_offset = addr - aligned_addr;
+    let word = ptrace::read(pid, aligned_addr as ptrace::AddressType)? as u64;
+    let masked_word = word & !(0xff << (byte_offset * 8));
+    let updated_word = masked_word | ((byte as u64) << (byte_offset * 8));
+    unsafe {
+        ptrace::write(pid, aligned_addr as ptrace::AddressType, updated_word as *mut std::ffi::c_void)
+    }
 }
 
 fn align_addr_to_word(addr: usize) -> usize {
@@ -44,11 +235,20 @@ fn align_addr_to_word(addr: usize) -> usize {
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints: HashMap<usize, u8>) -> Option<Inferior> {
+    /// an error is encountered. `stdio` redirects its stdout and stderr, which are otherwise
+    /// inherited from deet.
+    pub fn new(
+        target: &str,
+        args: &Vec<String>,
+        breakpoints: &[usize],
+        stdio: Option<(Stdio, Stdio)>,
+    ) -> Option<Inferior> {
         // TODO: implement me!
         let mut cmd = Command::new(target);
         cmd.args(args);
+        if let Some((stdout, stderr)) = stdio {
+            cmd.stdout(stdout).stderr(stderr);
+        }
         unsafe {
             cmd.pre_exec(child_traceme);
         }
@@ -66,10 +266,12 @@ impl Inferior {
                         return None;
                     },
                 }
-                let mut res = Inferior {child, breakpoints};
+                let pid = Pid::from_raw(child.id() as i32);
+                ptrace::setoptions(pid, trace_options()).ok()?;
+                let mut res = Inferior::with_threads(pid, false, &[pid]);
                 // Install breakpoints
-                for breakpoint in res.breakpoints.clone() {
-                    if let Err(_) = res.insert_breakpoint(breakpoint.0) {
+                for breakpoint in breakpoints {
+                    if res.insert_breakpoint(*breakpoint).is_err() {
                         eprintln!("Breakpoint Install failed!");
                     }
                 }
@@ -80,94 +282,987 @@ impl Inferior {
         }
     }
 
+    /// Attaches to the already-running process `pid` with PTRACE_ATTACH. Returns None if the
+    /// process can't be traced.
+    pub fn attach(pid: Pid, breakpoints: &[usize]) -> Option<Inferior> {
+        if let Err(err) = ptrace::attach(pid) {
+            eprintln!("Could not attach to process {}: {}", pid, err);
+            return None;
+        }
+        match waitpid(pid, None).ok()? {
+            WaitStatus::Stopped(_, Signal::SIGSTOP) => {}
+            other => {
+                eprintln!("Unexpected status while attaching: {:?}", other);
+                return None;
+            }
+        }
+        // Every other thread has to be attached to separately
+        let mut tids = vec![pid];
+        for entry in fs::read_dir(format!("/proc/{}/task", pid)).ok()?.flatten() {
+            let tid = match entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+                Some(tid) if tid != pid.as_raw() => Pid::from_raw(tid),
+                _ => continue,
+            };
+            if ptrace::attach(tid).is_ok() && waitpid(tid, Some(WaitPidFlag::__WALL)).is_ok() {
+                tids.push(tid);
+            }
+        }
+        for tid in &tids {
+            ptrace::setoptions(*tid, trace_options()).ok()?;
+        }
+        let mut res = Inferior::with_threads(pid, true, &tids);
+        for breakpoint in breakpoints {
+            if res.insert_breakpoint(*breakpoint).is_err() {
+                eprintln!("Breakpoint Install failed!");
+            }
+        }
+        Some(res)
+    }
+
+    /// Sets the signal the current thread receives when it is next resumed, replacing whatever
+    /// the signal policies decided
+    pub fn set_pending_signal(&mut self, signal: Option<Signal>) {
+        match signal {
+            Some(signal) => self.pending_signals.insert(self.tid, signal),
+            None => self.pending_signals.remove(&self.tid),
+        };
+    }
+
+    /// Wraps a core dump, so that it can be inspected like a stopped process
+    pub fn from_core(core: CoreDump) -> Inferior {
+        let mut inferior = Inferior::with_threads(core.pid(), false, &core.tids());
+        inferior.core = Some(core);
+        inferior
+    }
+
+    /// An inferior with no process behind it, for evaluating expressions that don't read memory
+    /// or registers
+    #[cfg(test)]
+    pub fn without_process() -> Inferior {
+        Inferior::with_threads(Pid::from_raw(0), false, &[])
+    }
+
+    /// Returns the core dump, if this inferior is one rather than a live process
+    pub fn core(&self) -> Option<&CoreDump> {
+        self.core.as_ref()
+    }
+
+    /// Writes a core file of the process as it is now, with the current thread first
+    pub fn generate_core(&self, path: &str) -> Result<(), std::io::Error> {
+        self.ensure_live()?;
+        let mut threads = vec![(self.tid, self.getregs(self.tid)?)];
+        for thread in &self.threads {
+            if thread.tid != self.tid {
+                threads.push((thread.tid, self.getregs(thread.tid)?));
+            }
+        }
+        coredump::write(path, self.pid, &threads, &self.breakpoints)
+    }
+
+    /// The auxiliary vector the kernel passed to the process (AT_ENTRY, AT_BASE, ...)
+    pub fn auxv(&self) -> HashMap<u64, u64> {
+        match &self.core {
+            Some(core) => solib::parse_auxv(core.auxv()),
+            None => solib::parse_auxv(&fs::read(format!("/proc/{}/auxv", self.pid)).unwrap_or_default()),
+        }
+    }
+
+    /// Tells a core dump where a shared library was mapped, so that the parts of it the kernel
+    /// left out of the core can be read from the library's file. Live processes need nothing.
+    pub fn library_loaded(&mut self, path: &str, bias: usize) {
+        if let Some(core) = self.core.as_mut() {
+            core.map_library(path, bias);
+        }
+    }
+
+    /// A core dump can be inspected, but not run or modified
+    fn ensure_live(&self) -> Result<(), nix::Error> {
+        match self.core {
+            Some(_) => Err(nix::Error::ESRCH),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the registers of a thread
+    fn getregs(&self, tid: Pid) -> Result<libc::user_regs_struct, nix::Error> {
+        match &self.core {
+            Some(core) => core.registers(tid).ok_or(nix::Error::ESRCH),
+            None => ptrace::getregs(tid),
+        }
+    }
+
+    /// Reads the word at `addr`, which must be aligned
+    fn peek(&self, addr: usize) -> Result<i64, nix::Error> {
+        match &self.core {
+            Some(core) => core
+                .read_memory(addr, size_of::<usize>())
+                .map(|bytes| i64::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or(nix::Error::EIO),
+            None => ptrace::read(self.tid(), addr as ptrace::AddressType),
+        }
+    }
+
+    /// Replaces the signal handling policies (see the `handle` command)
+    pub fn set_signal_table(&mut self, signals: SignalTable) {
+        self.signals = signals;
+    }
+
+    fn with_threads(pid: Pid, attached: bool, tids: &[Pid]) -> Inferior {
+        let mut inferior = Inferior {
+            pid,
+            tid: pid,
+            threads: Vec::new(),
+            next_thread_number: 1,
+            running: HashSet::new(),
+            attached,
+            breakpoints: HashMap::new(),
+            signals: SignalTable::default(),
+            pending_signals: HashMap::new(),
+            pending_sigstops: HashSet::new(),
+            rewound: HashSet::new(),
+            follow_fork_mode: FollowForkMode::Parent,
+            read_watchpoints: HashMap::new(),
+            core: None,
+        };
+        for tid in tids {
+            inferior.add_thread(*tid);
+        }
+        inferior
+    }
+
+    fn add_thread(&mut self, tid: Pid) -> usize {
+        let number = self.next_thread_number;
+        self.next_thread_number += 1;
+        self.threads.push(Thread { number, tid });
+        number
+    }
+
+    /// Forgets about a thread that exited. If it was the current thread, switches to another one.
+    fn remove_thread(&mut self, tid: Pid) {
+        if let Some(idx) = self.threads.iter().position(|thread| thread.tid == tid) {
+            let thread = self.threads.remove(idx);
+            println!("[Thread {} ({}) exited]", thread.number, tid);
+        }
+        self.running.remove(&tid);
+        self.pending_signals.remove(&tid);
+        self.pending_sigstops.remove(&tid);
+        if tid == self.tid {
+            self.tid = self.threads.first().map_or(self.pid, |thread| thread.tid);
+        }
+    }
+
+    /// Sets what happens to the inferior's children when it forks
+    pub fn set_follow_fork_mode(&mut self, mode: FollowForkMode) {
+        self.follow_fork_mode = mode;
+    }
+
+    /// Resumes the inferior, delivering any pending signals. With `single_step`, only the current
+    /// thread executes one instruction (PTRACE_SINGLESTEP); otherwise every thread continues.
+    fn resume(&mut self, single_step: bool) -> Result<(), nix::Error> {
+        if single_step {
+            return self.resume_thread(self.tid, true);
+        }
+        for thread in self.threads.clone() {
+            if self.running.contains(&thread.tid) {
+                continue;
+            }
+            if let Err(err) = self.resume_thread(thread.tid, false) {
+                // Another thread may have exited behind our back; only the current one matters
+                if thread.tid == self.tid {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resumes one thread, single-stepping it if it's the current thread and `single_step` is set
+    fn resume_thread(&mut self, tid: Pid, single_step: bool) -> Result<(), nix::Error> {
+        self.ensure_live()?;
+        let signal = self.pending_signals.remove(&tid);
+        self.rewound.remove(&tid);
+        if single_step && tid == self.tid {
+            ptrace::step(tid, signal)?;
+        } else {
+            ptrace::cont(tid, signal)?;
+        }
+        self.running.insert(tid);
+        Ok(())
+    }
+
+    /// Waits for the inferior to stop after `resume`. Thread creation, forks, signals whose policy
+    /// is `nostop`, and threads exiting are all handled here without returning to the caller.
+    /// Once some thread does stop, the other threads are stopped as well and that thread becomes
+    /// the current thread.
+    fn wait_resumed(&mut self, single_step: bool) -> Result<Status, nix::Error> {
+        loop {
+            let status = waitpid(None, Some(WaitPidFlag::__WALL))?;
+            let tid = match status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            self.running.remove(&tid);
+            match status {
+                WaitStatus::Exited(_, exit_code) if tid == self.pid => {
+                    return Ok(Status::Exited(exit_code))
+                }
+                WaitStatus::Signaled(_, signal, _) if tid == self.pid => {
+                    return Ok(Status::Signaled(signal))
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    let was_current = tid == self.tid;
+                    self.remove_thread(tid);
+                    if was_current && self.running.is_empty() {
+                        // We were stepping the thread that exited; stop in some other thread
+                        self.stop_all_threads()?;
+                        let regs = self.getregs(self.tid())?;
+                        return Ok(Status::Stopped(Signal::SIGTRAP, regs.rip as usize));
+                    }
+                }
+                WaitStatus::PtraceEvent(_, _, event) => {
+                    if let Some(status) = self.handle_ptrace_event(tid, event, single_step)? {
+                        return Ok(status);
+                    }
+                }
+                WaitStatus::Stopped(_, Signal::SIGSTOP) if self.pending_sigstops.remove(&tid) => {
+                    self.resume_thread(tid, single_step)?;
+                }
+                WaitStatus::Stopped(_, signal) => {
+                    if signal != Signal::SIGTRAP {
+                        let policy = self.signals.get(signal);
+                        if policy.pass {
+                            self.pending_signals.insert(tid, signal);
+                        }
+                        if !policy.stop {
+                            if policy.print {
+                                println!("Child received signal {}", signal.as_str());
+                            }
+                            self.resume_thread(tid, single_step)?;
+                            continue;
+                        }
+                    }
+                    if tid != self.tid {
+                        self.tid = tid;
+                        if let Some(thread) = self.threads.iter().find(|thread| thread.tid == tid) {
+                            println!("[Switching to thread {} ({})]", thread.number, tid);
+                        }
+                    }
+                    self.stop_all_threads()?;
+                    let regs = self.getregs(self.tid())?;
+                    return Ok(Status::Stopped(signal, regs.rip as usize));
+                }
+                _ => self.resume_thread(tid, single_step)?,
+            }
+        }
+    }
+
+    /// Handles a PTRACE_EVENT stop of thread `tid`. Returns a status if the event should be
+    /// reported to the debugger; otherwise the inferior has been resumed.
+    fn handle_ptrace_event(
+        &mut self,
+        tid: Pid,
+        event: i32,
+        single_step: bool,
+    ) -> Result<Option<Status>, nix::Error> {
+        match event {
+            libc::PTRACE_EVENT_CLONE => {
+                let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                // The new thread starts out stopped with a SIGSTOP
+                waitpid(new_tid, Some(WaitPidFlag::__WALL))?;
+                self.copy_debug_registers(tid, new_tid)?;
+                let number = self.add_thread(new_tid);
+                println!("[New thread {} ({})]", number, new_tid);
+                if !single_step {
+                    self.resume_thread(new_tid, false)?;
+                }
+            }
+            libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK => {
+                let child = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                waitpid(child, Some(WaitPidFlag::__WALL))?;
+                let shares_memory = event == libc::PTRACE_EVENT_VFORK;
+                if self.follow_fork_mode == FollowForkMode::Child {
+                    self.follow_child(child, shares_memory)?;
+                    self.resume_thread(child, single_step)?;
+                    return Ok(None);
+                }
+                // A vfork child shares our memory until it execs or exits, so this takes our
+                // breakpoints out of both; PTRACE_EVENT_VFORK_DONE puts them back
+                self.remove_breakpoints_from(child)?;
+                ptrace::detach(child, None)?;
+                if shares_memory {
+                    println!("[Detaching after vfork from child process {}]", child);
+                } else {
+                    println!("[Detaching after fork from child process {}]", child);
+                }
+            }
+            libc::PTRACE_EVENT_VFORK_DONE => self.reinsert_breakpoints(tid)?,
+            libc::PTRACE_EVENT_EXEC => {
+                // Only the main thread survives an exec, and the old address space (including our
+                // breakpoints) is gone
+                for thread in self.threads.clone() {
+                    if thread.tid != self.pid {
+                        self.remove_thread(thread.tid);
+                    }
+                }
+                self.tid = self.pid;
+                self.running.clear();
+                self.pending_sigstops.clear();
+                self.breakpoints.clear();
+                let exe = fs::read_link(format!("/proc/{}/exe", self.pid))
+                    .map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                return Ok(Some(Status::Execed(exe)));
+            }
+            _ => {}
+        }
+        self.resume_thread(tid, single_step)?;
+        Ok(None)
+    }
+
+    /// Switches from debugging this process to debugging its freshly forked `child`. The parent
+    /// is cleaned of breakpoints and detached.
+    fn follow_child(&mut self, child: Pid, shares_memory: bool) -> Result<(), nix::Error> {
+        self.stop_all_threads()?;
+        let parent_tid = self.tid;
+        self.remove_breakpoints_from(parent_tid)?;
+        if shares_memory {
+            // The child had the same bytes removed; they'll be re-inserted when it execs
+            self.breakpoints.clear();
+        }
+        for slot in 0..4 {
+            self.clear_hw_watchpoint(slot).ok();
+        }
+        for thread in self.threads.clone() {
+            ptrace::detach(thread.tid, self.pending_signals.remove(&thread.tid)).ok();
+        }
+        println!("[Attaching after fork to child process {}]", child);
+        self.pid = child;
+        self.tid = child;
+        self.threads.clear();
+        self.running.clear();
+        self.pending_signals.clear();
+        self.pending_sigstops.clear();
+        self.add_thread(child);
+        Ok(())
+    }
+
+    /// Restores the original bytes under our breakpoints in another process's copy of our memory
+    fn remove_breakpoints_from(&self, pid: Pid) -> Result<(), nix::Error> {
+        for (addr, original_byte) in &self.breakpoints {
+            poke_byte(pid, *addr, *original_byte)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the 0xcc of every breakpoint back through the stopped thread `tid`, once a vfork
+    /// child that had them removed from our shared memory is done with it
+    fn reinsert_breakpoints(&self, tid: Pid) -> Result<(), nix::Error> {
+        for addr in self.breakpoints.keys() {
+            poke_byte(tid, *addr, 0xcc)?;
+        }
+        Ok(())
+    }
+
+    /// All-stop: interrupts every thread that is still running and waits until it has stopped.
+    /// Whatever those threads stopped for instead of our SIGSTOP is remembered for later.
+    fn stop_all_threads(&mut self) -> Result<(), nix::Error> {
+        for tid in self.running.clone() {
+            unsafe {
+                libc::syscall(libc::SYS_tgkill, self.pid.as_raw(), tid.as_raw(), libc::SIGSTOP);
+            }
+            self.running.remove(&tid);
+            match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                Ok(WaitStatus::Stopped(_, Signal::SIGSTOP)) | Err(_) => {}
+                Ok(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {
+                    // Probably hit a breakpoint; back up so that it hits it again when resumed
+                    let mut regs = ptrace::getregs(tid)?;
+                    if self.breakpoints.contains_key(&(regs.rip as usize - 1)) {
+                        regs.rip -= 1;
+                        ptrace::setregs(tid, regs)?;
+                        self.rewound.insert(tid);
+                    }
+                    self.pending_sigstops.insert(tid);
+                }
+                Ok(WaitStatus::Stopped(_, signal)) => {
+                    self.pending_signals.insert(tid, signal);
+                    self.pending_sigstops.insert(tid);
+                }
+                Ok(WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE)) => {
+                    let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                    waitpid(new_tid, Some(WaitPidFlag::__WALL))?;
+                    self.copy_debug_registers(tid, new_tid)?;
+                    let number = self.add_thread(new_tid);
+                    println!("[New thread {} ({})]", number, new_tid);
+                    self.pending_sigstops.insert(tid);
+                }
+                Ok(WaitStatus::PtraceEvent(_, _, event)) => {
+                    if event == libc::PTRACE_EVENT_VFORK_DONE {
+                        self.reinsert_breakpoints(tid)?;
+                    }
+                    self.pending_sigstops.insert(tid);
+                }
+                Ok(WaitStatus::Exited(..)) | Ok(WaitStatus::Signaled(..)) => self.remove_thread(tid),
+                Ok(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives a new thread the same hardware watchpoints as the thread that created it
+    fn copy_debug_registers(&self, from: Pid, to: Pid) -> Result<(), nix::Error> {
+        for index in [0, 1, 2, 3, 7] {
+            let value = ptrace::read_user(from, debug_register_offset(index))?;
+            unsafe {
+                ptrace::write_user(to, debug_register_offset(index), value as *mut std::ffi::c_void)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Threads of the inferior, in order of creation
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// The thread that registers are read from
+    pub fn tid(&self) -> Pid {
+        self.tid
+    }
+
+    /// Makes the thread with the given number the current thread. Returns false if there is no
+    /// such thread.
+    pub fn select_thread(&mut self, number: usize) -> bool {
+        match self.threads.iter().find(|thread| thread.number == number) {
+            Some(thread) => {
+                self.tid = thread.tid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reads the instruction pointer of any thread
+    pub fn thread_pc(&self, tid: Pid) -> Result<usize, nix::Error> {
+        Ok(self.getregs(tid)?.rip as usize)
+    }
+
+    /// Returns the address that caused the fault the inferior is stopped with (e.g. the address
+    /// of an invalid memory access for SIGSEGV)
+    pub fn fault_address(&self) -> Result<usize, nix::Error> {
+        if let Some(core) = &self.core {
+            return core.fault_address().ok_or(nix::Error::EINVAL);
+        }
+        let siginfo = ptrace::getsiginfo(self.tid())?;
+        Ok(unsafe { siginfo.si_addr() } as usize)
+    }
+
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Returns true if this inferior was attached to rather than started by us
+    pub fn is_attached(&self) -> bool {
+        self.attached
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// If the inferior is sitting on one of our breakpoints, temporarily restores the original
+    /// byte, executes that single instruction and re-inserts the 0xcc. Returns Some(status) if
+    /// the inferior stopped for any other reason while doing so (e.g. it exited).
+    fn step_over_breakpoint(&mut self) -> Result<Option<Status>, nix::Error> {
+        let instruction_ptr = self.getregs(self.tid())?.rip as usize;
+        let rip_original_byte = match self.breakpoints.get(&instruction_ptr) {
+            Some(byte) => *byte,
+            None => return Ok(None),
+        };
+        if self.write_byte(instruction_ptr, rip_original_byte).is_err() {
+            eprintln!("Breakpoint: restore first byte failed!");
+            return Ok(None);
+        }
+        if let Err(er) = self.resume(true) {
+            eprintln!("Breakpoint: go to next instruction failed!");
+            return Err(er);
+        }
+        match self.wait_resumed(true)? {
+            Status::Stopped(Signal::SIGTRAP, rip) => {
+                self.write_byte(instruction_ptr, 0xcc)?;
+                // The instruction we stepped over may have tripped a watchpoint
+                if !self.skip_read_watchpoint_write()? && self.watchpoint_triggered()?.is_some() {
+                    return Ok(Some(Status::Stopped(Signal::SIGTRAP, rip)));
+                }
+                Ok(None)
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+            Status::Stopped(signal, rip) => {
+                self.write_byte(instruction_ptr, 0xcc)?;
+                Ok(Some(Status::Stopped(signal, rip)))
+            }
+            status => Ok(Some(status)),
+        }
     }
 
-    pub fn continue_execute(&mut self) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        let instruction_ptr = (regs.rip as usize) - 1;
-        if let Some(rip_original_byte) = self.breakpoints.get(&instruction_ptr) {
-            if self.write_byte(instruction_ptr, *rip_original_byte).is_ok() {
-                regs.rip = instruction_ptr as u64;
-                if let Err(er) = ptrace::setregs(self.pid(), regs) { 
+    /// Other threads may be sitting on breakpoints they already reported (e.g. before the user
+    /// switched threads); step them past before everything resumes.
+    fn step_other_threads_over_breakpoints(&mut self) -> Result<Option<Status>, nix::Error> {
+        let current = self.tid;
+        for thread in self.threads.clone() {
+            if thread.tid == current || self.rewound.contains(&thread.tid) {
+                continue;
+            }
+            self.tid = thread.tid;
+            let status = self.step_over_breakpoint();
+            if !matches!(status, Ok(None)) {
+                return status;
+            }
+        }
+        self.tid = current;
+        Ok(None)
+    }
+
+    /// When the inferior traps on a 0xcc we inserted, %rip points one byte past the breakpoint.
+    /// Rewind it so that the inferior is stopped *at* the breakpoint address.
+    fn rewind_breakpoint(&mut self, status: Status) -> Result<Status, nix::Error> {
+        if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+            let breakpoint_addr = rip - 1;
+            if self.breakpoints.contains_key(&breakpoint_addr) && self.watchpoint_triggered()?.is_none() {
+                let mut regs = self.getregs(self.tid())?;
+                regs.rip = breakpoint_addr as u64;
+                if let Err(er) = ptrace::setregs(self.tid(), regs) {
                     eprintln!("Breakpoint: set rip = rip-1 failed!");
                     return Err(er);
                 }
-                if let Err(er) = ptrace::step(self.pid(), None) {
-                    eprintln!("Breakpoint: go to next instruction failed!");
-                    return Err(er);
-                }
-                match self.wait(None) {
-                    Ok(status) => {
-                        if let Status::Stopped(_, _) = status {
-                            if let Err(er) = self.write_byte(instruction_ptr, 0xcc) {
-                                return Err(er);
-                            };
-                        }
-                    } ,
-                    Err(er) => return Err(er),
+                return Ok(Status::Stopped(Signal::SIGTRAP, breakpoint_addr));
+            }
+        }
+        Ok(status)
+    }
+
+    pub fn continue_execute(&mut self, should_stop: &mut StopFilter) -> Result<Status, nix::Error> {
+        loop {
+            if let Some(status) = self.step_other_threads_over_breakpoints()? {
+                return Ok(status);
+            }
+            if let Some(status) = self.step_over_breakpoint()? {
+                return Ok(status);
+            }
+            self.resume(false)?;
+            let status = self.wait_resumed(false)?;
+            let status = self.rewind_breakpoint(status)?;
+            match status {
+                Status::Stopped(Signal::SIGTRAP, addr)
+                    if self.breakpoints.contains_key(&addr) && !should_stop(self, addr) => {}
+                Status::Stopped(Signal::SIGTRAP, _) if self.skip_read_watchpoint_write()? => {}
+                _ => return Ok(status),
+            }
+        }
+    }
+
+    /// Executes exactly one machine instruction.
+    pub fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        if self.breakpoints.contains_key(&(self.getregs(self.tid())?.rip as usize)) {
+            if let Some(status) = self.step_over_breakpoint()? {
+                return Ok(status);
+            }
+            let regs = self.getregs(self.tid())?;
+            return Ok(Status::Stopped(Signal::SIGTRAP, regs.rip as usize));
+        }
+        self.resume(true)?;
+        let status = self.wait_resumed(true)?;
+        if let Status::Stopped(Signal::SIGTRAP, _) = status {
+            self.skip_read_watchpoint_write()?;
+        }
+        Ok(status)
+    }
+
+    /// Runs the inferior until it reaches `addr` with its stack pointer at or above `min_rsp`
+    /// (so that recursive calls reaching the same address don't count). Stops early if some other
+    /// breakpoint or signal is hit.
+    fn run_to_address(
+        &mut self,
+        addr: usize,
+        min_rsp: usize,
+        should_stop: &mut StopFilter,
+    ) -> Result<Status, nix::Error> {
+        let temporary = !self.breakpoints.contains_key(&addr);
+        if temporary && self.insert_breakpoint(addr).is_err() {
+            return Err(nix::Error::EIO);
+        }
+        let status = loop {
+            let status = self.continue_execute(&mut |inferior, stop_addr| {
+                stop_addr == addr || should_stop(inferior, stop_addr)
+            })?;
+            if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+                if rip == addr && (self.getregs(self.tid())?.rsp as usize) < min_rsp {
+                    continue;
                 }
+            }
+            break status;
+        };
+        if temporary {
+            if let Status::Stopped(_, _) = status {
+                self.remove_breakpoint(addr)?;
             } else {
-                eprintln!("Breakpoint: restore first byte failed!");
+                self.breakpoints.remove(&addr);
+            }
+        }
+        Ok(status)
+    }
+
+    /// Returns (return address, canonical frame address) of the frame that %rip is currently in.
+    /// The CFA is the value %rsp will have once the function returns.
+    fn current_frame_return(&self, debug_data: &DwarfData) -> Result<(usize, usize), nix::Error> {
+        let regs = self.getregs(self.tid())?;
+        let unwound = debug_data.unwind_frame(&Registers::from_user_regs(&regs), &|addr| {
+            self.read_word(addr)
+        });
+        if let Some((cfa, caller)) = unwound {
+            if let Some(return_addr) = caller.pc() {
+                return Ok((return_addr as usize, cfa as usize));
+            }
+        }
+        // No CFI; assume the usual frame pointer prologue
+        let rip = regs.rip as usize;
+        let func_start = debug_data
+            .get_function_containing_addr(rip)
+            .map(|func| func.address);
+        // Inside the prologue %rbp still belongs to the caller, so go through %rsp instead
+        let cfa = if func_start.is_none() || func_start == Some(rip) {
+            regs.rsp as usize + 8
+        } else if func_start == Some(rip - 1) {
+            // push %rbp has executed, mov %rsp,%rbp has not
+            regs.rsp as usize + 16
+        } else {
+            regs.rbp as usize + 16
+        };
+        let return_addr = self.peek(cfa - 8)? as usize;
+        Ok((return_addr, cfa))
+    }
+
+    /// Reads a register by name (without the leading `$`), e.g. `rip` or `rax`. Returns None if
+    /// there is no such register.
+    pub fn get_register(&self, name: &str) -> Result<Option<u64>, nix::Error> {
+        let mut regs = self.getregs(self.tid())?;
+        Ok(register_mut(&mut regs, name).map(|reg| *reg))
+    }
+
+    /// Overwrites a register by name. Returns false if there is no such register.
+    pub fn set_register(&mut self, name: &str, value: u64) -> Result<bool, nix::Error> {
+        self.ensure_live()?;
+        let mut regs = self.getregs(self.tid())?;
+        match register_mut(&mut regs, name) {
+            Some(reg) => *reg = value,
+            None => return Ok(false),
+        }
+        ptrace::setregs(self.tid(), regs)?;
+        Ok(true)
+    }
+
+    /// Returns (name, value) for every register, in the order `info registers` shows them
+    pub fn get_registers(&self) -> Result<Vec<(&'static str, u64)>, nix::Error> {
+        let mut regs = self.getregs(self.tid())?;
+        Ok(REGISTER_NAMES
+            .iter()
+            .map(|name| (*name, *register_mut(&mut regs, name).unwrap()))
+            .collect())
+    }
+
+    /// Returns the innermost frame of the current thread, without unwinding any further. This is
+    /// all that is needed to look at its variables.
+    pub fn current_frame(&self, debug_data: &DwarfData) -> Result<Frame, nix::Error> {
+        let regs = self.getregs(self.tid())?;
+        let (_, cfa) = self.current_frame_return(debug_data)?;
+        let inlined = debug_data.get_inline_chain(regs.rip as usize).len().saturating_sub(1);
+        Ok(Frame {
+            level: 0,
+            inline_depth: inlined,
+            is_caller: false,
+            pc: regs.rip as usize,
+            cfa: Some(cfa),
+            registers: Registers::from_user_regs(&regs),
+        })
+    }
+
+    /// Continues until the current function returns to its caller.
+    pub fn finish(
+        &mut self,
+        debug_data: &DwarfData,
+        should_stop: &mut StopFilter,
+    ) -> Result<Status, nix::Error> {
+        let (return_addr, cfa) = self.current_frame_return(debug_data)?;
+        self.run_to_address(return_addr, cfa, should_stop)
+    }
+
+    /// Steps until the inferior reaches the beginning of a different source line. If
+    /// `step_over_calls` is set, called functions are run to completion (`next`); otherwise
+    /// stepping descends into any called function that has debugging information (`step`).
+    pub fn step_line(
+        &mut self,
+        debug_data: &DwarfData,
+        step_over_calls: bool,
+        should_stop: &mut StopFilter,
+    ) -> Result<Status, nix::Error> {
+        let start_regs = self.getregs(self.tid())?;
+        let start_line = debug_data.get_line_from_addr(start_regs.rip as usize);
+        let start_func = debug_data.get_function_from_addr(start_regs.rip as usize);
+        let mut rip = start_regs.rip as usize;
+        loop {
+            let call_length = self.call_length_at(rip);
+            let mut status = self.step_instruction()?;
+            let prev_rip = rip;
+            rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => rip,
+                other => return Ok(other),
+            };
+            if self.watchpoint_triggered()?.is_some() {
+                return Ok(status);
+            }
+            let call_return = call_length.map(|len| prev_rip + len);
+            if let Some(return_addr) = call_return.filter(|_| {
+                step_over_calls || debug_data.get_line_from_addr(rip).is_none()
+            }) {
+                // Run the callee until it returns to the instruction after the call
+                let rsp = self.getregs(self.tid())?.rsp as usize;
+                status = self.run_to_address(return_addr, rsp + 8, should_stop)?;
+                match status {
+                    Status::Stopped(Signal::SIGTRAP, addr) if addr == return_addr => rip = addr,
+                    other => return Ok(other),
+                }
+            }
+            let current_line = debug_data.get_line_from_addr(rip);
+            if current_line.is_none() && debug_data.get_function_containing_addr(rip).is_some() {
+                // Compiler-generated code (line 0) in the middle of a function
+                continue;
+            }
+            if current_line.is_none() {
+                // We returned into code without debugging information (e.g. from main into libc)
+                return self.continue_execute(should_stop);
+            }
+            let same_line = current_line.as_ref().map(|line| (&line.file, line.number))
+                == start_line.as_ref().map(|line| (&line.file, line.number));
+            if debug_data.is_line_boundary(rip)
+                && (!same_line || debug_data.get_function_from_addr(rip) != start_func)
+            {
+                return Ok(status);
             }
         }
-        ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+    }
+
+    /// If the instruction at `addr` is a call, returns its length
+    fn call_length_at(&self, addr: usize) -> Option<usize> {
+        // Near the end of a mapping, the rest of the (4 KiB) page may be shorter than the longest
+        // instruction
+        let bytes = self
+            .read_memory(addr, disassembler::MAX_INSTRUCTION_LEN)
+            .or_else(|_| self.read_memory(addr, 4096 - addr % 4096))
+            .ok()?;
+        disassembler::call_length(&bytes)
     }
 
     pub fn kill(&mut self) {
-        if self.child.kill().is_ok() {
-            self.wait(None).ok();
+        if self.core.is_some() {
+            return;
+        }
+        if signal::kill(self.pid(), Signal::SIGKILL).is_ok() {
+            // Reap every thread; the main thread is reported last
+            while let Ok(status) = waitpid(None, Some(WaitPidFlag::__WALL)) {
+                if matches!(status, WaitStatus::Exited(..) | WaitStatus::Signaled(..))
+                    && status.pid() == Some(self.pid())
+                {
+                    break;
+                }
+            }
             println!("Killing running inferior (pid {})", self.pid());
         }
     }
 
+    /// Removes every trace of the debugger from the process (0xcc bytes, debug registers) and lets
+    /// it continue untraced.
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        self.ensure_live()?;
+        let addrs: Vec<usize> = self.breakpoints.keys().copied().collect();
+        for addr in addrs {
+            self.remove_breakpoint(addr)?;
+        }
+        for slot in 0..4 {
+            self.clear_hw_watchpoint(slot)?;
+        }
+        for thread in self.threads.clone() {
+            ptrace::detach(thread.tid, self.pending_signals.remove(&thread.tid))?;
+        }
+        println!("Detaching from process {}", self.pid());
+        Ok(())
+    }
+
+    /// Unwinds the stack, returning frames from the innermost outwards. Functions inlined into a
+    /// frame get frames of their own, sharing its registers. The walk ends at main, at the
+    /// outermost frame, or when a frame can't be unwound.
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<Vec<Frame>, nix::Error> {
+        let mut regs = Registers::from_user_regs(&self.getregs(self.tid())?);
+        let mut frames: Vec<Frame> = Vec::new();
+        while let Some(pc) = regs.pc() {
+            if pc == 0 || frames.len() >= MAX_FRAMES {
+                break;
+            }
+            let unwound = debug_data
+                .unwind_frame(&regs, &|addr| self.read_word(addr))
+                .or_else(|| self.unwind_with_frame_pointer(&regs));
+            let is_caller = !frames.is_empty();
+            let lookup_pc = if is_caller { pc as usize - 1 } else { pc as usize };
+            let chain = debug_data.get_inline_chain(lookup_pc);
+            for inline_depth in (0..chain.len().max(1)).rev() {
+                frames.push(Frame {
+                    level: frames.len(),
+                    inline_depth,
+                    is_caller,
+                    pc: pc as usize,
+                    cfa: unwound.as_ref().map(|(cfa, _)| *cfa as usize),
+                    registers: regs.clone(),
+                });
+            }
+            let in_main = chain.last().is_some_and(|(name, _)| name == "main");
+            let rsp = regs.rsp();
+            match unwound {
+                // The stack grows down, so each caller's frame must be above its callee's
+                Some((_, caller)) if !in_main && caller.rsp() > rsp => regs = caller,
+                _ => break,
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Fallback for code without CFI: assume %rbp points at the saved %rbp, with the return
+    /// address right above it.
+    fn unwind_with_frame_pointer(&self, regs: &Registers) -> Option<(u64, Registers)> {
+        let rbp = regs.rbp()?;
+        if rbp == 0 || rbp < regs.rsp()? {
+            return None;
+        }
+        let mut caller = Registers::default();
+        caller.set_frame_registers(self.read_word(rbp + 8)?, rbp + 16, self.read_word(rbp)?);
+        Some((rbp + 16, caller))
+    }
+
     pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid())?;
-        let mut instruction_ptr = regs.rip as usize;
-        let mut base_ptr = regs.rbp as usize;
-        let mut debug_current_line = debug_data.get_line_from_addr(instruction_ptr);
-        let mut debug_current_func = debug_data.get_function_from_addr(instruction_ptr);
-        loop {
-            // println!("%rip register: {:#x}, %rbp register: {:#x}", instruction_ptr, base_ptr);
-            let func_name = debug_current_func.as_ref().unwrap();
-            let file_name = &debug_current_line.as_ref().unwrap().file;
-            let code_line = debug_current_line.as_ref().unwrap().number;
-            println!("{} ({}:{})", func_name, file_name, code_line);
-            if func_name == "main" { break; }
-            instruction_ptr = ptrace::read(self.pid(), (base_ptr + 8) as ptrace::AddressType)? as usize;
-            base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as usize;
-            debug_current_line = debug_data.get_line_from_addr(instruction_ptr);
-            debug_current_func = debug_data.get_function_from_addr(instruction_ptr);
-        }        
+        for frame in self.backtrace(debug_data)? {
+            println!("{}", self.describe_frame(debug_data, &frame));
+        }
+        Ok(())
+    }
+
+    /// Formats a frame the way `backtrace` shows it, e.g.
+    /// `#1  0x00000000004011ee in func1 (a=42) at function_calls.c:18`
+    pub fn describe_frame(&self, debug_data: &DwarfData, frame: &Frame) -> String {
+        let lookup_pc = frame.lookup_pc();
+        let mut description = format!("#{:<3}", frame.level);
+        // The caller of an inlined function is at the call, not at an address of its own
+        if (frame.is_caller || !debug_data.is_line_boundary(frame.pc))
+            && !frame.has_inlined_callee(debug_data)
+        {
+            description += &format!("{:#018x} in ", frame.pc);
+        }
+        let (func_name, line) = frame.function(debug_data);
+        description += func_name.as_deref().unwrap_or("??");
+        let args: Vec<String> = debug_data
+            .get_local_variables(lookup_pc, frame.inline_depth)
+            .iter()
+            .filter(|var| var.is_parameter)
+            .map(|var| {
+                let read_memory = |addr, len| self.read_memory(addr, len).ok();
+                let value = match location::read_variable(var, Some(frame), debug_data, &read_memory) {
+                    Ok(Some(bytes)) => {
+                        ValueFormatter::new(debug_data, &read_memory).format(&var.entity_type, &bytes)
+                    }
+                    Ok(None) => "<optimized out>".to_string(),
+                    Err(_) => "<unavailable>".to_string(),
+                };
+                format!("{}={}", var.name, value)
+            })
+            .collect();
+        description += &format!(" ({})", args.join(", "));
+        if let Some(line) = line {
+            description += &format!(" at {}:{}", line.file, line.number);
+        }
+        description
+    }
+
+    /// Reads a word of inferior memory, for the unwinder
+    fn read_word(&self, addr: u64) -> Option<u64> {
+        self.peek(addr as usize)
+            .ok()
+            .map(|word| word as u64)
+    }
+
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < addr + len {
+            let word = self.peek(word_addr)? as u64;
+            for (i, byte) in word.to_le_bytes().iter().enumerate() {
+                let byte_addr = word_addr + i;
+                if byte_addr >= addr && byte_addr < addr + len {
+                    // Hide our own breakpoints from whoever is reading
+                    bytes.push(*self.breakpoints.get(&byte_addr).unwrap_or(byte));
+                }
+            }
+            word_addr += size_of::<usize>();
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a NUL-terminated string of at most `max_len` bytes.
+    pub fn read_c_string(&self, addr: usize, max_len: usize) -> Result<String, nix::Error> {
+        let mut bytes = Vec::new();
+        while bytes.len() < max_len {
+            let chunk = self.read_memory(addr + bytes.len(), size_of::<usize>())?;
+            match chunk.iter().position(|byte| *byte == 0) {
+                Some(end) => {
+                    bytes.extend_from_slice(&chunk[..end]);
+                    break;
+                }
+                None => bytes.extend_from_slice(&chunk),
+            }
+        }
+        bytes.truncate(max_len);
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    /// Writes `bytes` to the inferior's memory at `addr`. Bytes covered by one of our breakpoints
+    /// update the saved original byte instead, so that the 0xcc stays in place.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        self.ensure_live()?;
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < addr + bytes.len() {
+            let mut word =
+                (self.peek(word_addr)? as u64).to_le_bytes();
+            for (i, byte) in word.iter_mut().enumerate() {
+                let byte_addr = word_addr + i;
+                if byte_addr >= addr && byte_addr < addr + bytes.len() {
+                    let val = bytes[byte_addr - addr];
+                    match self.breakpoints.get_mut(&byte_addr) {
+                        Some(original_byte) => *original_byte = val,
+                        None => *byte = val,
+                    }
+                }
+            }
+            unsafe { ptrace::write(
+                self.tid(),
+                word_addr as ptrace::AddressType,
+                u64::from_le_bytes(word) as *mut std::ffi::c_void,
+            ) }?;
+            word_addr += size_of::<usize>();
+        }
         Ok(())
     }
 
     pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        self.ensure_live()?;
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+        let word = self.peek(aligned_addr)? as u64;
+        let orig_byte = (word >> (8 * byte_offset)) & 0xff;
+        let masked_word = word & !(0xff << (8 * byte_offset));
+        let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
         unsafe { ptrace::write(
-            self.pid(),
+            self.tid(),
             aligned_addr as ptrace::AddressType,
             updated_word as *mut std::ffi::c_void,
         ) }?;
@@ -175,6 +1270,9 @@ impl Inferior {
     }
 
     pub fn insert_breakpoint(&mut self, addr: usize) -> Result<(), ()> {
+        if self.breakpoints.contains_key(&addr) {
+            return Ok(());
+        }
         if let Ok(original_byte) = self.write_byte(addr, 0xcc) {
             self.breakpoints.insert(addr, original_byte);
             Ok(())
@@ -182,4 +1280,107 @@ impl Inferior {
             Err(())
         }
     }
+
+    /// Programs hardware debug register `slot` (0-3) to trap when `len` bytes at `addr` are
+    /// accessed. `len` must be 1, 2, 4 or 8 and `addr` must be aligned to it.
+    pub fn set_hw_watchpoint(
+        &mut self,
+        slot: usize,
+        addr: usize,
+        len: usize,
+        kind: WatchKind,
+    ) -> Result<(), nix::Error> {
+        self.ensure_live()?;
+        let len_bits: u64 = match len {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            4 => 0b11,
+            _ => return Err(nix::Error::EINVAL),
+        };
+        // x86 can't trap on reads only; rwatch uses read/write and the debugger tells them apart
+        let rw_bits: u64 = match kind {
+            WatchKind::Write => 0b01,
+            WatchKind::Read | WatchKind::Access => 0b11,
+        };
+        if kind == WatchKind::Read {
+            let contents = self.read_memory(addr, len)?;
+            self.read_watchpoints.insert(slot, (addr, len, contents));
+        } else {
+            self.read_watchpoints.remove(&slot);
+        }
+        // Debug registers are per thread
+        for thread in &self.threads {
+            unsafe {
+                ptrace::write_user(thread.tid, debug_register_offset(slot), addr as *mut std::ffi::c_void)?;
+            }
+            let mut dr7 = ptrace::read_user(thread.tid, debug_register_offset(7))? as u64;
+            dr7 &= !(0b1111 << (16 + slot * 4));
+            dr7 |= ((len_bits << 2) | rw_bits) << (16 + slot * 4);
+            dr7 |= 1 << (slot * 2);
+            unsafe {
+                ptrace::write_user(thread.tid, debug_register_offset(7), dr7 as *mut std::ffi::c_void)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Disables hardware debug register `slot`
+    pub fn clear_hw_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        self.ensure_live()?;
+        self.read_watchpoints.remove(&slot);
+        for thread in &self.threads {
+            let mut dr7 = ptrace::read_user(thread.tid, debug_register_offset(7))? as u64;
+            dr7 &= !(1 << (slot * 2));
+            dr7 &= !(0b1111 << (16 + slot * 4));
+            unsafe {
+                ptrace::write_user(thread.tid, debug_register_offset(7), dr7 as *mut std::ffi::c_void)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the debug register slot that caused the current stop, according to DR6
+    fn watchpoint_triggered(&self) -> Result<Option<usize>, nix::Error> {
+        self.ensure_live()?;
+        let dr6 = ptrace::read_user(self.tid(), debug_register_offset(6))? as u64;
+        Ok((0..4).find(|slot| dr6 & (1 << slot) != 0))
+    }
+
+    /// Like watchpoint_triggered, but also clears DR6 so that the hit isn't reported twice
+    pub fn take_watchpoint_hit(&mut self) -> Result<Option<usize>, nix::Error> {
+        let slot = self.watchpoint_triggered()?;
+        unsafe {
+            ptrace::write_user(self.tid(), debug_register_offset(6), std::ptr::null_mut())?;
+        }
+        Ok(slot)
+    }
+
+    /// Returns true if the current stop is a write to a location watched by rwatch, which x86
+    /// can't tell apart from a read until we look at the value. The hit is cleared from DR6 and
+    /// the new value noted, so that the inferior can carry on as if nothing happened.
+    fn skip_read_watchpoint_write(&mut self) -> Result<bool, nix::Error> {
+        let slot = match self.watchpoint_triggered()? {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        let (addr, len) = match self.read_watchpoints.get(&slot) {
+            Some((addr, len, _)) => (*addr, *len),
+            None => return Ok(false),
+        };
+        let contents = self.read_memory(addr, len)?;
+        match self.read_watchpoints.get_mut(&slot) {
+            Some((_, _, old_contents)) if *old_contents != contents => *old_contents = contents,
+            _ => return Ok(false),
+        }
+        self.take_watchpoint_hit()?;
+        Ok(true)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(original_byte) = self.breakpoints.remove(&addr) {
+            self.write_byte(addr, original_byte)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,265 @@
+//! The `--interpreter=json` mode, for driving deet from another program.
+//!
+//! Rather than threading a second output path through every command, the debugger keeps printing
+//! text as usual while its stdout and stderr are redirected into pipes. When a command finishes,
+//! whatever it printed is collected and emitted as part of a single JSON object on the real
+//! stdout:
+//!
+//!     {"type":"result","id":1,"command":"break func2","status":"done","output":"Set breakpoint..."}
+//!
+//! A command that printed anything to stderr has status "error", with that text as its "message".
+//! Stops and exits of the inferior are emitted as separate `{"type":"event",...}` objects as they
+//! happen, and so is whatever the inferior itself writes to its stdout and stderr:
+//!
+//!     {"type":"event","event":"output","stream":"stdout","text":"hello\n"}
+//!
+//! Input is one command per line, either as plain text or as
+//! `{"id": <anything>, "command": "<text>"}`, in which case the id is echoed back in the result.
+
+use nix::unistd::{dup, dup2, pipe, read, write};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::process::Stdio;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Written to a captured stream to find out when everything before it has been collected
+const SYNC_MARKER: &[u8] = b"\x1bdeet-sync\x1b";
+
+#[derive(Default)]
+struct CaptureState {
+    /// Bytes read before the most recent sync marker
+    collected: Vec<u8>,
+    /// Bytes read since then
+    pending: Vec<u8>,
+    syncs_seen: usize,
+}
+
+/// Redirects a file descriptor into a pipe that a background thread keeps draining, so that the
+/// writer never blocks on a full pipe.
+struct Capture {
+    fd: RawFd,
+    state: Arc<(Mutex<CaptureState>, Condvar)>,
+    syncs_sent: usize,
+}
+
+impl Capture {
+    fn new(fd: RawFd) -> Result<Capture, nix::Error> {
+        let (read_end, write_end) = pipe()?;
+        dup2(write_end, fd)?;
+        nix::unistd::close(write_end)?;
+        let state = Arc::new((Mutex::new(CaptureState::default()), Condvar::new()));
+        let reader_state = state.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(len) = read(read_end, &mut buf) {
+                if len == 0 {
+                    break;
+                }
+                let (lock, condvar) = &*reader_state;
+                let mut state = lock.lock().unwrap();
+                state.pending.extend_from_slice(&buf[..len]);
+                while let Some(pos) = find(&state.pending, SYNC_MARKER) {
+                    let before: Vec<u8> = state.pending.drain(..pos + SYNC_MARKER.len()).collect();
+                    state.collected.extend_from_slice(&before[..pos]);
+                    state.syncs_seen += 1;
+                    condvar.notify_all();
+                }
+            }
+        });
+        Ok(Capture {
+            fd,
+            state,
+            syncs_sent: 0,
+        })
+    }
+
+    /// Returns everything written to the stream since the last call
+    fn take(&mut self) -> String {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        let mut marker = SYNC_MARKER;
+        while !marker.is_empty() {
+            match write(self.fd, marker) {
+                Ok(len) => marker = &marker[len..],
+                Err(nix::Error::EINTR) => continue,
+                Err(_) => return String::new(),
+            }
+        }
+        self.syncs_sent += 1;
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        while state.syncs_seen < self.syncs_sent {
+            state = condvar.wait(state).unwrap();
+        }
+        String::from_utf8_lossy(&std::mem::take(&mut state.collected)).to_string()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Writes one JSON object on its own line
+fn emit_to(out: &Mutex<File>, value: Value) {
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", value);
+    let _ = out.flush();
+}
+
+/// Creates a pipe for the inferior to write one of its streams to, and a thread that forwards
+/// whatever arrives as output events. Returns the write end.
+fn forward_program_output(out: Arc<Mutex<File>>, stream: &'static str) -> Result<RawFd, nix::Error> {
+    let (read_end, write_end) = pipe()?;
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while let Ok(len) = read(read_end, &mut buf) {
+            if len == 0 {
+                break;
+            }
+            emit_to(
+                &out,
+                json!({
+                    "type": "event",
+                    "event": "output",
+                    "stream": stream,
+                    "text": String::from_utf8_lossy(&buf[..len]),
+                }),
+            );
+        }
+    });
+    Ok(write_end)
+}
+
+pub struct JsonInterpreter {
+    /// The original stdout, where the JSON objects go. The threads forwarding the inferior's
+    /// output write to it too.
+    out: Arc<Mutex<File>>,
+    stdout: Capture,
+    stderr: Capture,
+    /// Write ends of the pipes the inferior's stdout and stderr go to. They are kept apart from
+    /// deet's own output, which ends up in command results.
+    program_stdout: RawFd,
+    program_stderr: RawFd,
+    /// Id of the request read most recently (null for plain text commands)
+    request_id: Value,
+    /// Id and text of the command being run, if any
+    current: Option<(Value, String)>,
+}
+
+impl JsonInterpreter {
+    /// Starts capturing stdout and stderr. Anything printed from here on ends up in JSON objects.
+    pub fn new() -> Result<JsonInterpreter, nix::Error> {
+        let out = Arc::new(Mutex::new(unsafe { File::from_raw_fd(dup(1)?) }));
+        Ok(JsonInterpreter {
+            program_stdout: forward_program_output(out.clone(), "stdout")?,
+            program_stderr: forward_program_output(out.clone(), "stderr")?,
+            out,
+            stdout: Capture::new(1)?,
+            stderr: Capture::new(2)?,
+            request_id: Value::Null,
+            current: None,
+        })
+    }
+
+    /// Writes one JSON object on its own line
+    pub fn emit(&mut self, value: Value) {
+        emit_to(&self.out, value);
+    }
+
+    /// Stdout and stderr for a newly started inferior, so that its output is emitted as events
+    /// rather than mixed up with the results of commands
+    pub fn program_stdio(&self) -> Result<(Stdio, Stdio), nix::Error> {
+        let stdout = unsafe { File::from_raw_fd(dup(self.program_stdout)?) };
+        let stderr = unsafe { File::from_raw_fd(dup(self.program_stderr)?) };
+        Ok((Stdio::from(stdout), Stdio::from(stderr)))
+    }
+
+    /// Announces that deet has loaded the target and is ready for commands, along with anything
+    /// printed while getting there
+    pub fn ready(&mut self, target: &str) {
+        let output = self.stdout.take();
+        let errors = self.stderr.take();
+        self.emit(json!({
+            "type": "ready",
+            "target": target,
+            "output": output,
+            "errors": errors,
+        }));
+    }
+
+    /// Reads the next command from stdin, returning its text. Returns None at end of input.
+    pub fn read_command(&mut self) -> Option<String> {
+        loop {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if !line.starts_with('{') {
+                self.request_id = Value::Null;
+                return Some(line.to_string());
+            }
+            let request: Value = match serde_json::from_str(line) {
+                Ok(request) => request,
+                Err(err) => {
+                    self.emit(json!({
+                        "type": "result",
+                        "status": "error",
+                        "message": format!("Invalid request: {}", err),
+                    }));
+                    continue;
+                }
+            };
+            match request.get("command").and_then(Value::as_str) {
+                Some(command) => {
+                    self.request_id = request.get("id").cloned().unwrap_or(Value::Null);
+                    return Some(command.to_string());
+                }
+                None => self.emit(json!({
+                    "type": "result",
+                    "id": request.get("id").cloned().unwrap_or(Value::Null),
+                    "status": "error",
+                    "message": "Request has no \"command\"",
+                })),
+            }
+        }
+    }
+
+    /// Notes that `command` is about to run. `from_request` says whether it came from stdin (as
+    /// opposed to a command file), in which case the request's id goes into the result.
+    pub fn start_command(&mut self, command: &str, from_request: bool) {
+        let id = if from_request {
+            std::mem::take(&mut self.request_id)
+        } else {
+            Value::Null
+        };
+        self.current = Some((id, command.to_string()));
+    }
+
+    /// Emits the result of the command that just ran, if there is one
+    pub fn finish_command(&mut self) {
+        let (id, command) = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        let output = self.stdout.take();
+        let errors = self.stderr.take();
+        let mut result = json!({
+            "type": "result",
+            "id": id,
+            "command": command,
+            "status": if errors.is_empty() { "done" } else { "error" },
+            "output": output,
+        });
+        if !errors.is_empty() {
+            result["message"] = Value::String(errors.trim_end().to_string());
+        }
+        self.emit(result);
+    }
+}
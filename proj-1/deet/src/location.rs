@@ -0,0 +1,168 @@
+//! Finding variables at run time by evaluating their DWARF locations.
+//!
+//! Unoptimized code keeps every variable at a fixed offset from the frame base, but optimizing
+//! compilers move them around: into registers, split across several places (DW_OP_piece), or
+//! somewhere different depending on the pc (location lists). Sometimes the value no longer exists
+//! at all, and the variable has been "optimized out".
+
+use crate::dwarf_data::{raw_value, DwarfData, Location, LocationExpression, Variable};
+use crate::inferior::Frame;
+use gimli::{EndianSlice, Evaluation, EvaluationResult, Piece, RunTimeEndian, Value};
+
+/// Where to find the value of a variable in a particular frame
+pub enum Place {
+    Address(usize),
+    /// The value isn't in memory (it is in registers, or computed), but these are its bytes
+    Value(Vec<u8>),
+    OptimizedOut,
+}
+
+/// Works out where `var` is in `frame` (None for globals). `read_memory` reads inferior memory,
+/// which location expressions may need to look at.
+pub fn locate(
+    var: &Variable,
+    frame: Option<&Frame>,
+    debug_data: &DwarfData,
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> Result<Place, String> {
+    let size = var.entity_type.size;
+    let expression = match &var.location {
+        Location::Address(addr) => return Ok(Place::Address(*addr)),
+        Location::FramePointerOffset(offset) => {
+            let frame_base = frame
+                .and_then(|frame| frame.frame_base(debug_data))
+                .ok_or(format!("Cannot access memory for {}", var.name))?;
+            return Ok(Place::Address((frame_base as isize + offset) as usize));
+        }
+        Location::Constant(bytes) => {
+            let mut bytes = bytes.clone();
+            bytes.resize(size, 0);
+            return Ok(Place::Value(bytes));
+        }
+        Location::Expression(expression) => expression,
+        Location::List(ranges) => {
+            let pc = frame.ok_or("No frame selected.")?.lookup_pc();
+            match ranges
+                .iter()
+                .find(|(start, end, _)| *start <= pc && pc < *end)
+            {
+                Some((_, _, expression)) => expression,
+                None => return Ok(Place::OptimizedOut),
+            }
+        }
+    };
+    evaluate(expression, size, frame, debug_data, read_memory)
+}
+
+/// Reads the bytes of `var` in `frame`, or returns None if it has been optimized out
+pub fn read_variable(
+    var: &Variable,
+    frame: Option<&Frame>,
+    debug_data: &DwarfData,
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> Result<Option<Vec<u8>>, String> {
+    match locate(var, frame, debug_data, read_memory)? {
+        Place::Address(addr) => match read_memory(addr, var.entity_type.size) {
+            Some(bytes) => Ok(Some(bytes)),
+            None => Err(format!("Cannot access memory at address {:#x}", addr)),
+        },
+        Place::Value(bytes) => Ok(Some(bytes)),
+        Place::OptimizedOut => Ok(None),
+    }
+}
+
+/// Evaluates a location expression for a value of `size` bytes. Registers come from the frame,
+/// so only those the unwinder recovered are available in outer frames; the others (and the vector
+/// registers, which we don't track) count as optimized out.
+fn evaluate(
+    expression: &LocationExpression,
+    size: usize,
+    frame: Option<&Frame>,
+    debug_data: &DwarfData,
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> Result<Place, String> {
+    let register = |register: u16| frame.and_then(|frame| frame.registers.get(register));
+    let read = |addr: u64, len: usize| {
+        read_memory(addr as usize, len)
+            .ok_or(format!("Cannot access memory at address {:#x}", addr))
+    };
+    let bytes = EndianSlice::new(&expression.bytes, RunTimeEndian::Little);
+    let mut evaluation = Evaluation::new(bytes, expression.encoding);
+    let mut result = evaluation.evaluate();
+    loop {
+        result = match result.map_err(|err| format!("Invalid location expression: {}", err))? {
+            EvaluationResult::Complete => break,
+            EvaluationResult::RequiresMemory { address, size, .. } => {
+                let value = raw_value(&read(address, size as usize)?);
+                evaluation.resume_with_memory(Value::Generic(value))
+            }
+            EvaluationResult::RequiresRegister { register: reg, .. } => match register(reg.0) {
+                Some(value) => evaluation.resume_with_register(Value::Generic(value)),
+                None => return Ok(Place::OptimizedOut),
+            },
+            EvaluationResult::RequiresFrameBase => {
+                let frame_base = frame
+                    .and_then(|frame| frame.frame_base(debug_data))
+                    .ok_or("Could not find the frame base.")?;
+                evaluation.resume_with_frame_base(frame_base as u64)
+            }
+            EvaluationResult::RequiresCallFrameCfa => {
+                let cfa = frame
+                    .and_then(|frame| frame.cfa)
+                    .ok_or("Could not find the CFA.")?;
+                evaluation.resume_with_call_frame_cfa(cfa as u64)
+            }
+            EvaluationResult::RequiresRelocatedAddress(address) => evaluation
+                .resume_with_relocated_address(address.wrapping_add(expression.bias as u64)),
+            // The value a register had on entry to the function, which is long gone
+            EvaluationResult::RequiresEntryValue(_) => return Ok(Place::OptimizedOut),
+            EvaluationResult::RequiresTls(_) => {
+                return Err("Thread-local variables are not supported.".to_string())
+            }
+            _ => return Err("Unsupported location expression.".to_string()),
+        };
+    }
+    let pieces = evaluation.result();
+    if let [Piece {
+        location: gimli::Location::Address { address },
+        size_in_bits: None,
+        ..
+    }] = pieces.as_slice()
+    {
+        return Ok(Place::Address(*address as usize));
+    }
+    // Anything else is put together from its pieces. A value with pieces missing is treated as
+    // entirely optimized out.
+    let mut bytes = Vec::new();
+    for piece in &pieces {
+        let len = match piece.size_in_bits {
+            Some(bits) => (bits as usize).div_ceil(8),
+            None => size.saturating_sub(bytes.len()),
+        };
+        let mut piece_bytes = match &piece.location {
+            gimli::Location::Empty | gimli::Location::ImplicitPointer { .. } => {
+                return Ok(Place::OptimizedOut)
+            }
+            gimli::Location::Register { register: reg } => match register(reg.0) {
+                Some(value) => value.to_le_bytes().to_vec(),
+                None => return Ok(Place::OptimizedOut),
+            },
+            gimli::Location::Address { address } => read(*address, len)?,
+            gimli::Location::Value { value } => value_bytes(*value),
+            gimli::Location::Bytes { value } => value.to_vec(),
+        };
+        piece_bytes.resize(len, 0);
+        bytes.extend(piece_bytes);
+    }
+    bytes.resize(size, 0);
+    Ok(Place::Value(bytes))
+}
+
+/// The little-endian bytes of a value computed by DW_OP_stack_value
+fn value_bytes(value: Value) -> Vec<u8> {
+    match value {
+        Value::F32(value) => value.to_le_bytes().to_vec(),
+        Value::F64(value) => value.to_le_bytes().to_vec(),
+        value => value.to_u64(!0).unwrap_or(0).to_le_bytes().to_vec(),
+    }
+}
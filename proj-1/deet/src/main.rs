@@ -1,24 +1,130 @@
+mod breakpoint;
+mod coredump;
 mod debugger;
 mod debugger_command;
+mod disassembler;
+mod expression;
 mod inferior;
+mod interpreter;
+mod location;
 mod dwarf_data;
+mod gdbserver;
 mod gimli_wrapper;
+mod signals;
+mod solib;
+mod unwind;
+mod value_format;
 
 use crate::debugger::Debugger;
+use crate::interpreter::JsonInterpreter;
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
+use std::fs;
+
+/// Prints how to invoke deet and exits
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [options] <target program> [core file]", program);
+    println!("       {} [options] -p <pid>", program);
+    println!("       {} --gdbserver [host]:<port> <target program> [args...]", program);
+    println!("Options: --batch, -x <command file>, --interpreter=json");
+    std::process::exit(1);
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    let mut target = None;
+    let mut core = None;
+    let mut attach_pid = None;
+    let mut scripts = Vec::new();
+    let mut batch = false;
+    let mut json = false;
+    let mut gdbserver = None;
+    let mut program_args = Vec::new();
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-x" => match rest.next() {
+                Some(script) => scripts.push(script.clone()),
+                None => usage(&args[0]),
+            },
+            "--batch" | "-batch" => batch = true,
+            "--interpreter=json" => json = true,
+            "--gdbserver" => match rest.next() {
+                Some(address) => gdbserver = Some(address.clone()),
+                None => usage(&args[0]),
+            },
+            "-p" => {
+                let pid = rest.next().unwrap_or_else(|| usage(&args[0]));
+                match pid.parse::<i32>() {
+                    Ok(pid) => match fs::read_link(format!("/proc/{}/exe", pid)) {
+                        Ok(exe) => {
+                            target = Some(exe.to_string_lossy().to_string());
+                            attach_pid = Some(pid);
+                        }
+                        Err(err) => {
+                            println!("Could not find the executable of process {}: {}", pid, err);
+                            std::process::exit(1);
+                        }
+                    },
+                    Err(_) => {
+                        println!("Invalid pid {}", pid);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ if target.is_none() && !arg.starts_with('-') => {
+                target = Some(arg.clone());
+                // As with gdbserver, everything after the target is passed to it
+                if gdbserver.is_some() {
+                    program_args = rest.by_ref().cloned().collect();
+                }
+            }
+            _ if core.is_none() && target.is_some() && !arg.starts_with('-') => {
+                core = Some(arg.clone())
+            }
+            _ => usage(&args[0]),
+        }
     }
-    let target = &args[1];
+    let target = target.unwrap_or_else(|| usage(&args[0]));
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    if let Some(address) = gdbserver {
+        std::process::exit(gdbserver::serve(&address, &target, &program_args));
+    }
+
+    // Capture output from the start, so that even symbol loading goes out as JSON
+    let interpreter = if json {
+        match JsonInterpreter::new() {
+            Ok(interpreter) => Some(interpreter),
+            Err(err) => {
+                println!("Could not start the JSON interpreter: {}", err);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let mut debugger = Debugger::new(&target);
+    // The DWARF dump is for people; it would only get in the way of JSON output
+    match interpreter {
+        Some(interpreter) => debugger.set_interpreter(interpreter),
+        None => debugger.print_debug_info(),
+    }
+    if let Some(pid) = attach_pid {
+        debugger.attach(pid);
+    }
+    if let Some(core) = core {
+        debugger.load_core(&core);
+    }
+    // Queue the scripts last-first, since each one is queued ahead of the others
+    for script in scripts.iter().rev() {
+        if debugger.source(script).is_err() {
+            std::process::exit(1);
+        }
+    }
+    debugger.set_batch(batch);
+    debugger.run();
 }
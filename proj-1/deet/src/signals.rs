@@ -0,0 +1,104 @@
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// What to do when the inferior receives a signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalPolicy {
+    /// Stop and return to the prompt
+    pub stop: bool,
+    /// Mention that the signal arrived (implied by `stop`)
+    pub print: bool,
+    /// Deliver the signal to the inferior when it resumes, rather than discarding it
+    pub pass: bool,
+}
+
+impl SignalPolicy {
+    fn new(stop: bool, print: bool, pass: bool) -> SignalPolicy {
+        SignalPolicy { stop, print, pass }
+    }
+}
+
+/// Per-signal policies, as changed by the `handle` command. Signals without an entry use the
+/// default policy (stop, print and pass).
+#[derive(Debug, Clone)]
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl Default for SignalTable {
+    fn default() -> Self {
+        let mut policies = HashMap::new();
+        // Signals the debugger itself uses are never passed on
+        policies.insert(Signal::SIGTRAP, SignalPolicy::new(true, true, false));
+        policies.insert(Signal::SIGINT, SignalPolicy::new(true, true, false));
+        // Signals that are part of normal operation for many programs
+        for signal in [
+            Signal::SIGALRM,
+            Signal::SIGURG,
+            Signal::SIGCHLD,
+            Signal::SIGWINCH,
+            Signal::SIGPROF,
+            Signal::SIGIO,
+        ] {
+            policies.insert(signal, SignalPolicy::new(false, false, true));
+        }
+        SignalTable { policies }
+    }
+}
+
+impl SignalTable {
+    /// Stops on every signal without passing any of them on, for when something else (a remote
+    /// gdb) decides what to do with them
+    pub fn report_all() -> SignalTable {
+        let policies = Signal::iterator()
+            .map(|signal| (signal, SignalPolicy::new(true, false, false)))
+            .collect();
+        SignalTable { policies }
+    }
+
+    pub fn get(&self, signal: Signal) -> SignalPolicy {
+        self.policies
+            .get(&signal)
+            .copied()
+            .unwrap_or(SignalPolicy::new(true, true, true))
+    }
+
+    /// Applies `handle` keywords (stop, nostop, print, noprint, pass, nopass, ignore, noignore)
+    /// to a signal. Returns the offending keyword if one isn't recognized.
+    pub fn update(&mut self, signal: Signal, keywords: &[String]) -> Result<(), String> {
+        let mut policy = self.get(signal);
+        for keyword in keywords {
+            match keyword.as_str() {
+                "stop" => {
+                    policy.stop = true;
+                    policy.print = true;
+                }
+                "nostop" => policy.stop = false,
+                "print" => policy.print = true,
+                "noprint" => {
+                    policy.print = false;
+                    policy.stop = false;
+                }
+                "pass" | "noignore" => policy.pass = true,
+                "nopass" | "ignore" => policy.pass = false,
+                other => return Err(other.to_string()),
+            }
+        }
+        self.policies.insert(signal, policy);
+        Ok(())
+    }
+}
+
+/// Parses a signal given as `SIGUSR1`, `USR1` or a number
+pub fn parse_signal(text: &str) -> Option<Signal> {
+    if let Ok(number) = text.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = text.to_uppercase();
+    if name.starts_with("SIG") {
+        Signal::from_str(&name).ok()
+    } else {
+        Signal::from_str(&format!("SIG{}", name)).ok()
+    }
+}
@@ -0,0 +1,76 @@
+//! Finding the shared libraries loaded into the inferior, using the dynamic linker's debugging
+//! interface (see <link.h>).
+//!
+//! The dynamic linker keeps a `struct r_debug` (exported as `_r_debug`) whose `r_map` heads a
+//! linked list of `struct link_map`, one per loaded object, giving its path and load bias. Whenever
+//! it is about to change the list, and again once it is done, it calls the empty function
+//! `_dl_debug_state`, so that a debugger with a breakpoint there can take a fresh look.
+
+use crate::inferior::Inferior;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+/// Offsets of r_map and r_state in `struct r_debug`
+const R_DEBUG_MAP: usize = 8;
+const R_DEBUG_STATE: usize = 24;
+/// r_state once the list is consistent again (as opposed to RT_ADD or RT_DELETE)
+const RT_CONSISTENT: u32 = 0;
+/// Offsets of l_addr, l_name and l_next in `struct link_map`
+const LINK_MAP_ADDR: usize = 0;
+const LINK_MAP_NAME: usize = 8;
+const LINK_MAP_NEXT: usize = 24;
+/// Longest list we are willing to follow, in case it is corrupted into a loop
+const MAX_OBJECTS: usize = 4096;
+const PATH_MAX: usize = 4096;
+
+/// A shared library in the dynamic linker's list
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedObject {
+    pub path: String,
+    /// Difference between the library's runtime addresses and the addresses in its file
+    pub bias: usize,
+}
+
+/// Parses the contents of /proc/<pid>/auxv (or an NT_AUXV note): pairs of words, ending with
+/// AT_NULL.
+pub fn parse_auxv(bytes: &[u8]) -> HashMap<u64, u64> {
+    bytes
+        .chunks_exact(2 * size_of::<u64>())
+        .map(|pair| {
+            let key = u64::from_le_bytes(pair[..8].try_into().unwrap());
+            let value = u64::from_le_bytes(pair[8..].try_into().unwrap());
+            (key, value)
+        })
+        .take_while(|(key, _)| *key != libc::AT_NULL)
+        .collect()
+}
+
+/// Reads the list of shared libraries from the `struct r_debug` at `r_debug`. Returns None while
+/// the dynamic linker is in the middle of changing it.
+pub fn read_link_map(inferior: &Inferior, r_debug: usize) -> Option<Vec<LoadedObject>> {
+    let read_word = |addr: usize| -> Option<usize> {
+        let bytes = inferior.read_memory(addr, size_of::<usize>()).ok()?;
+        Some(usize::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    if read_word(r_debug + R_DEBUG_STATE)? as u32 != RT_CONSISTENT {
+        return None;
+    }
+    let mut objects = Vec::new();
+    let mut link_map = read_word(r_debug + R_DEBUG_MAP)?;
+    while link_map != 0 && objects.len() < MAX_OBJECTS {
+        let name = read_word(link_map + LINK_MAP_NAME)?;
+        let path = match name {
+            0 => String::new(),
+            addr => inferior.read_c_string(addr, PATH_MAX).ok()?,
+        };
+        // The main program has no name, and the vDSO has no file
+        if path.starts_with('/') {
+            objects.push(LoadedObject {
+                path,
+                bias: read_word(link_map + LINK_MAP_ADDR)?,
+            });
+        }
+        link_map = read_word(link_map + LINK_MAP_NEXT)?;
+    }
+    Some(objects)
+}
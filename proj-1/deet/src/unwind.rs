@@ -0,0 +1,252 @@
+//! Stack unwinding using the call frame information (CFI) in `.eh_frame` and `.debug_frame`.
+//!
+//! Every function has a table describing, for each instruction, how to compute the canonical frame
+//! address (CFA: the value of %rsp just before the `call` into the function) and where the caller's
+//! registers have been saved. Walking this table is what lets us produce backtraces through code
+//! compiled without frame pointers.
+
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, EndianSlice, Evaluation, EvaluationResult,
+    Register, RegisterRule, RunTimeEndian, UnwindContext, UnwindSection, UnwindTableRow, Value,
+};
+use object::{Object, ObjectSection};
+
+/// DWARF register number of the return address column (%rip) on x86-64
+const RETURN_ADDRESS: u16 = 16;
+/// DWARF register numbers of %rbp and %rsp
+const RBP: u16 = 6;
+const RSP: u16 = 7;
+/// Registers a callee must preserve in the x86-64 ABI: %rbx, %rbp and %r12-%r15. The others are
+/// clobbered by calls, so unless the CFI says where they went, a caller's values are lost.
+const CALLEE_SAVED: [u16; 6] = [3, RBP, 12, 13, 14, 15];
+/// Names of DWARF registers 0-16
+const REGISTER_NAMES: [&str; 17] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+
+/// Values of the x86-64 registers as DWARF numbers them: 0-15 are the general purpose registers
+/// (rax, rdx, rcx, rbx, rsi, rdi, rbp, rsp, r8-r15) and 16 is %rip. `None` means the value could
+/// not be recovered for this frame.
+#[derive(Debug, Clone, Default)]
+pub struct Registers([Option<u64>; 17]);
+
+impl Registers {
+    pub fn from_user_regs(regs: &libc::user_regs_struct) -> Registers {
+        Registers([
+            Some(regs.rax),
+            Some(regs.rdx),
+            Some(regs.rcx),
+            Some(regs.rbx),
+            Some(regs.rsi),
+            Some(regs.rdi),
+            Some(regs.rbp),
+            Some(regs.rsp),
+            Some(regs.r8),
+            Some(regs.r9),
+            Some(regs.r10),
+            Some(regs.r11),
+            Some(regs.r12),
+            Some(regs.r13),
+            Some(regs.r14),
+            Some(regs.r15),
+            Some(regs.rip),
+        ])
+    }
+
+    pub fn get(&self, register: u16) -> Option<u64> {
+        *self.0.get(register as usize)?
+    }
+
+    /// Looks up a register by name, e.g. `rbx`. Returns None if it isn't one of the registers
+    /// tracked here, and Some(None) if its value wasn't recovered.
+    pub fn get_by_name(&self, name: &str) -> Option<Option<u64>> {
+        let register = REGISTER_NAMES.iter().position(|known| *known == name)?;
+        Some(self.get(register as u16))
+    }
+
+    fn set(&mut self, register: u16, value: Option<u64>) {
+        if let Some(slot) = self.0.get_mut(register as usize) {
+            *slot = value;
+        }
+    }
+
+    /// Sets the registers a frame pointer walk recovers: pc, %rsp and %rbp
+    pub fn set_frame_registers(&mut self, pc: u64, rsp: u64, rbp: u64) {
+        self.set(RETURN_ADDRESS, Some(pc));
+        self.set(RSP, Some(rsp));
+        self.set(RBP, Some(rbp));
+    }
+
+    pub fn pc(&self) -> Option<u64> {
+        self.get(RETURN_ADDRESS)
+    }
+
+    pub fn rbp(&self) -> Option<u64> {
+        self.get(RBP)
+    }
+
+    pub fn rsp(&self) -> Option<u64> {
+        self.get(RSP)
+    }
+}
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// The raw CFI sections of an executable or shared library.
+pub struct CallFrameInfo {
+    endian: RunTimeEndian,
+    eh_frame: Vec<u8>,
+    debug_frame: Vec<u8>,
+    bases: BaseAddresses,
+    /// How far the object was moved from its file addresses when it was loaded
+    bias: u64,
+}
+
+impl CallFrameInfo {
+    pub fn load(object: &object::File, endian: RunTimeEndian) -> CallFrameInfo {
+        let section_data = |name: &str| {
+            object
+                .section_by_name(name)
+                .and_then(|section| section.uncompressed_data().ok())
+                .map(|data| data.into_owned())
+                .unwrap_or_default()
+        };
+        let section_address =
+            |name: &str| object.section_by_name(name).map(|section| section.address());
+        let mut bases = BaseAddresses::default();
+        if let Some(addr) = section_address(".eh_frame") {
+            bases = bases.set_eh_frame(addr);
+        }
+        if let Some(addr) = section_address(".eh_frame_hdr") {
+            bases = bases.set_eh_frame_hdr(addr);
+        }
+        if let Some(addr) = section_address(".text") {
+            bases = bases.set_text(addr);
+        }
+        if let Some(addr) = section_address(".got") {
+            bases = bases.set_got(addr);
+        }
+        CallFrameInfo {
+            endian,
+            eh_frame: section_data(".eh_frame"),
+            debug_frame: section_data(".debug_frame"),
+            bases,
+            bias: 0,
+        }
+    }
+
+    pub fn set_bias(&mut self, bias: u64) {
+        self.bias = bias;
+    }
+
+    /// Given the registers of a frame, computes that frame's CFA and the registers of its caller.
+    /// `read_memory` reads a word of inferior memory. Returns None if there is no CFI covering the
+    /// frame's pc. The caller's pc is None if this is the outermost frame.
+    pub fn unwind(
+        &self,
+        regs: &Registers,
+        read_memory: &dyn Fn(u64) -> Option<u64>,
+    ) -> Option<(u64, Registers)> {
+        let pc = regs.pc()?.wrapping_sub(self.bias);
+        let mut ctx = UnwindContext::new();
+        let eh_frame = EhFrame::new(&self.eh_frame, self.endian);
+        if let Ok(row) =
+            eh_frame.unwind_info_for_address(&self.bases, &mut ctx, pc, EhFrame::cie_from_offset)
+        {
+            return self.apply_row(row, regs, read_memory);
+        }
+        let debug_frame = DebugFrame::new(&self.debug_frame, self.endian);
+        if let Ok(row) = debug_frame.unwind_info_for_address(
+            &self.bases,
+            &mut ctx,
+            pc,
+            DebugFrame::cie_from_offset,
+        ) {
+            return self.apply_row(row, regs, read_memory);
+        }
+        None
+    }
+
+    fn apply_row(
+        &self,
+        row: &UnwindTableRow<Reader>,
+        regs: &Registers,
+        read_memory: &dyn Fn(u64) -> Option<u64>,
+    ) -> Option<(u64, Registers)> {
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                (regs.get(register.0)? as i64 + offset) as u64
+            }
+            CfaRule::Expression(expression) => {
+                self.evaluate(expression.0, regs, read_memory, None)?
+            }
+        };
+        // Callee-saved registers without a rule keep their value, and %rsp is the CFA by
+        // definition. Other registers without one weren't saved, and a return address without
+        // one marks the outermost frame.
+        let mut caller = regs.clone();
+        caller.set(RSP, Some(cfa));
+        caller.set(RETURN_ADDRESS, None);
+        for register in 0..=RETURN_ADDRESS {
+            let value = match row.register(Register(register)) {
+                RegisterRule::Undefined if register == RSP || CALLEE_SAVED.contains(&register) => {
+                    continue
+                }
+                RegisterRule::Undefined => None,
+                RegisterRule::Architectural => continue,
+                RegisterRule::SameValue => regs.get(register),
+                RegisterRule::Offset(offset) => read_memory((cfa as i64 + offset) as u64),
+                RegisterRule::ValOffset(offset) => Some((cfa as i64 + offset) as u64),
+                RegisterRule::Register(other) => regs.get(other.0),
+                RegisterRule::Expression(expression) => self
+                    .evaluate(expression.0, regs, read_memory, Some(cfa))
+                    .and_then(read_memory),
+                RegisterRule::ValExpression(expression) => {
+                    self.evaluate(expression.0, regs, read_memory, Some(cfa))
+                }
+            };
+            caller.set(register, value);
+        }
+        Some((cfa, caller))
+    }
+
+    /// Evaluates a DWARF expression from a CFI rule. Rule expressions (as opposed to CFA
+    /// expressions) start with the CFA pushed on the stack.
+    fn evaluate(
+        &self,
+        expression: Reader,
+        regs: &Registers,
+        read_memory: &dyn Fn(u64) -> Option<u64>,
+        cfa: Option<u64>,
+    ) -> Option<u64> {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 8,
+        };
+        let mut evaluation = Evaluation::new(expression, encoding);
+        if let Some(cfa) = cfa {
+            evaluation.set_initial_value(cfa);
+        }
+        let mut result = evaluation.evaluate().ok()?;
+        loop {
+            result = match result {
+                EvaluationResult::Complete => break,
+                EvaluationResult::RequiresMemory { address, .. } => {
+                    let value = read_memory(address)?;
+                    evaluation.resume_with_memory(Value::Generic(value)).ok()?
+                }
+                EvaluationResult::RequiresRegister { register, .. } => {
+                    let value = regs.get(register.0)?;
+                    evaluation.resume_with_register(Value::Generic(value)).ok()?
+                }
+                _ => return None,
+            };
+        }
+        match evaluation.result().first()?.location {
+            gimli::Location::Address { address } => Some(address),
+            _ => None,
+        }
+    }
+}
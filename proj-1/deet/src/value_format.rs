@@ -0,0 +1,338 @@
+//! Formatting values for `print`, following the type graph loaded from DWARF. C values look the
+//! way gdb prints them (`{x = 1, y = 2}`), and Rust values get gdb's Rust syntax. The standard
+//! library's `&str`, `String` and `Vec<T>` are shown by their contents rather than their internal
+//! layout, and enums (including `Option<T>`) by the variant their discriminant selects.
+
+use crate::dwarf_data::{DwarfData, Member, Type, TypeId, TypeKind, Variant};
+
+/// Most array elements or string characters printed, like gdb's `print elements`
+const MAX_ELEMENTS: usize = 200;
+/// How deeply nested values may get before we stop following them
+const MAX_DEPTH: usize = 8;
+
+pub struct ValueFormatter<'a> {
+    debug_data: &'a DwarfData,
+    /// Reads inferior memory, for values that live elsewhere (the contents of a `String`)
+    read_memory: &'a dyn Fn(usize, usize) -> Option<Vec<u8>>,
+}
+
+impl<'a> ValueFormatter<'a> {
+    pub fn new(
+        debug_data: &'a DwarfData,
+        read_memory: &'a dyn Fn(usize, usize) -> Option<Vec<u8>>,
+    ) -> ValueFormatter<'a> {
+        ValueFormatter {
+            debug_data,
+            read_memory,
+        }
+    }
+
+    /// Formats the raw (little-endian) bytes of a value of type `ty`
+    pub fn format(&self, ty: &Type, bytes: &[u8]) -> String {
+        self.format_nested(ty, bytes, 0)
+    }
+
+    fn format_nested(&self, ty: &Type, bytes: &[u8], depth: usize) -> String {
+        if depth > MAX_DEPTH {
+            return "...".to_string();
+        }
+        match &ty.kind {
+            TypeKind::Struct {
+                members,
+                template_params,
+            } => {
+                if ty.rust {
+                    if let Some(value) =
+                        self.format_rust_std(ty, members, template_params, bytes, depth)
+                    {
+                        return value;
+                    }
+                }
+                self.format_struct(&ty.name, ty.rust, members, bytes, depth)
+            }
+            TypeKind::Variants {
+                discriminant,
+                variants,
+            } => self.format_variants(ty, discriminant.as_ref(), variants, bytes, depth),
+            TypeKind::Enum(enumerators) => {
+                let value = ty.value_as_i64(bytes);
+                match enumerators
+                    .iter()
+                    .find(|(_, enumerator)| *enumerator == value)
+                {
+                    Some((name, _)) if ty.rust => format!("{}::{}", ty.name, name),
+                    Some((name, _)) => name.clone(),
+                    None => value.to_string(),
+                }
+            }
+            TypeKind::Array { element, count } => match self.debug_data.get_type(*element) {
+                Some(element) => self.format_array(ty, element, count.unwrap_or(0), bytes, depth),
+                None => "<unknown>".to_string(),
+            },
+            TypeKind::Base | TypeKind::Pointer(_) => ty.format_value(bytes),
+        }
+    }
+
+    /// Formats the members of a struct: `{x = 1, y = 2}` in C, `hello::Point {x: 1, y: 2}` or
+    /// `hello::Pair (1, 2)` in Rust
+    fn format_struct(
+        &self,
+        name: &str,
+        rust: bool,
+        members: &[Member],
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        let fields: Vec<(&str, String)> = members
+            .iter()
+            .map(|member| {
+                (
+                    member.name.as_str(),
+                    self.format_member(member, bytes, depth),
+                )
+            })
+            .collect();
+        if !rust {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| match *name {
+                    // Anonymous structs and unions
+                    "" => value.clone(),
+                    name => format!("{} = {}", name, value),
+                })
+                .collect();
+            return format!("{{{}}}", fields.join(", "));
+        }
+        format!("{} {}", name, rust_fields(&fields))
+            .trim_end()
+            .to_string()
+    }
+
+    /// Formats the variant of a Rust enum that its discriminant selects, e.g.
+    /// `hello::Shape::Rect{w: 2, h: 3}` or `core::option::Option<u32>::Some(5)`
+    fn format_variants(
+        &self,
+        ty: &Type,
+        discriminant: Option<&Member>,
+        variants: &[Variant],
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        let variant = match discriminant {
+            Some(discriminant) => {
+                let value = self
+                    .member_bytes(discriminant, bytes)
+                    .map(|(member_type, bytes)| member_type.value_as_i64(bytes) as u64);
+                variants
+                    .iter()
+                    .find(|variant| variant.value.is_some() && variant.value == value)
+                    .or_else(|| variants.iter().find(|variant| variant.value.is_none()))
+            }
+            // An enum with a single variant needs no discriminant
+            None => variants.first(),
+        };
+        let variant = match variant {
+            Some(variant) => &variant.member,
+            None => return "<invalid enum value>".to_string(),
+        };
+        let fields = match self.debug_data.get_type(variant.type_id).map(|ty| &ty.kind) {
+            Some(TypeKind::Struct { members, .. }) => members
+                .iter()
+                .map(|member| {
+                    (
+                        member.name.as_str(),
+                        self.format_member(member, bytes, depth),
+                    )
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        format!("{}::{}{}", ty.name, variant.name, rust_fields(&fields))
+    }
+
+    /// Formats the standard library types whose layout isn't worth showing. Returns None for
+    /// anything else.
+    fn format_rust_std(
+        &self,
+        ty: &Type,
+        members: &[Member],
+        template_params: &[(String, TypeId)],
+        bytes: &[u8],
+        depth: usize,
+    ) -> Option<String> {
+        let name = ty.name.as_str();
+        if name == "&str" || name == "&mut str" {
+            let (data, length) = self.slice_parts(members, bytes)?;
+            return Some(self.format_string(data, length));
+        }
+        if name == "alloc::string::String" {
+            let vec = members.iter().find(|member| member.name == "vec")?;
+            let (vec_type, vec_bytes) = self.member_bytes(vec, bytes)?;
+            let (data, length) = self.vec_parts(vec_type, vec_bytes)?;
+            return Some(self.format_string(data, length));
+        }
+        if name.starts_with("alloc::vec::Vec<") {
+            let (data, length) = self.vec_parts(ty, bytes)?;
+            let (_, element) = template_params.iter().find(|(param, _)| param == "T")?;
+            let element = self.debug_data.get_type(*element)?;
+            let elements = self.format_elements(element, data, length, depth);
+            return Some(format!("Vec(size={}) = {{{}}}", length, elements));
+        }
+        if name.starts_with("&[") || name.starts_with("&mut [") {
+            let (data, length) = self.slice_parts(members, bytes)?;
+            let data_ptr = members.iter().find(|member| member.name == "data_ptr")?;
+            let element = match self.debug_data.get_type(data_ptr.type_id)?.kind {
+                TypeKind::Pointer(Some(element)) => self.debug_data.get_type(element)?,
+                _ => return None,
+            };
+            let elements = self.format_elements(element, data, length, depth);
+            return Some(format!("{}(size={}) = {{{}}}", name, length, elements));
+        }
+        None
+    }
+
+    /// Reads the `data_ptr` and `length` of a Rust slice (`&str` or `&[T]`)
+    fn slice_parts(&self, members: &[Member], bytes: &[u8]) -> Option<(usize, usize)> {
+        let field = |name: &str| -> Option<usize> {
+            let member = members.iter().find(|member| member.name == name)?;
+            let (member_type, bytes) = self.member_bytes(member, bytes)?;
+            Some(member_type.value_as_i64(bytes) as usize)
+        };
+        Some((field("data_ptr")?, field("length")?))
+    }
+
+    /// Reads the data pointer and length of a `Vec`. The pointer is buried a few structs deep
+    /// (`buf.inner.ptr.pointer.pointer` at the moment), and the nesting keeps changing between
+    /// Rust versions, so we take the first pointer inside `buf`.
+    fn vec_parts(&self, ty: &Type, bytes: &[u8]) -> Option<(usize, usize)> {
+        let members = match &ty.kind {
+            TypeKind::Struct { members, .. } => members,
+            _ => return None,
+        };
+        let len = members.iter().find(|member| member.name == "len")?;
+        let (len_type, len_bytes) = self.member_bytes(len, bytes)?;
+        let buf = members.iter().find(|member| member.name == "buf")?;
+        let (buf_type, buf_bytes) = self.member_bytes(buf, bytes)?;
+        Some((
+            self.first_pointer(buf_type, buf_bytes, 0)?,
+            len_type.value_as_i64(len_bytes) as usize,
+        ))
+    }
+
+    /// Finds the value of the first pointer among a struct's (nested) members
+    fn first_pointer(&self, ty: &Type, bytes: &[u8], depth: usize) -> Option<usize> {
+        match &ty.kind {
+            TypeKind::Pointer(_) => Some(ty.value_as_i64(bytes) as usize),
+            TypeKind::Struct { members, .. } if depth < MAX_DEPTH => {
+                members.iter().find_map(|member| {
+                    let (member_type, bytes) = self.member_bytes(member, bytes)?;
+                    self.first_pointer(member_type, bytes, depth + 1)
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads up to MAX_ELEMENTS elements of an array and formats them, separated by commas
+    fn format_elements(&self, element: &Type, data: usize, length: usize, depth: usize) -> String {
+        let shown = length.min(MAX_ELEMENTS);
+        match (self.read_memory)(data, shown * element.size) {
+            Some(bytes) => self.format_element_bytes(element, &bytes, length, depth),
+            None => format!("<error: Cannot access memory at address {:#x}>", data),
+        }
+    }
+
+    /// Formats up to MAX_ELEMENTS of the `length` elements at the start of `bytes`, separated by
+    /// commas
+    fn format_element_bytes(
+        &self,
+        element: &Type,
+        bytes: &[u8],
+        length: usize,
+        depth: usize,
+    ) -> String {
+        let shown = length.min(MAX_ELEMENTS);
+        let mut elements: Vec<String> = (0..shown)
+            .map(
+                |i| match bytes.get(i * element.size..(i + 1) * element.size) {
+                    Some(bytes) => self.format_nested(element, bytes, depth + 1),
+                    None => "<unknown>".to_string(),
+                },
+            )
+            .collect();
+        if length > shown {
+            elements.push("...".to_string());
+        }
+        elements.join(", ")
+    }
+
+    /// Formats an array: `{1, 2, 3}` in C and `[1, 2, 3]` in Rust. C's char arrays usually hold
+    /// strings, so they are shown as text up to the first NUL.
+    fn format_array(
+        &self,
+        ty: &Type,
+        element: &Type,
+        count: usize,
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        if !ty.rust && element.size == 1 && element.name.contains("char") {
+            let text = &bytes[..count.min(bytes.len())];
+            let end = text
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(text.len());
+            return format!("{:?}", String::from_utf8_lossy(&text[..end]));
+        }
+        let elements = self.format_element_bytes(element, bytes, count, depth);
+        if ty.rust {
+            format!("[{}]", elements)
+        } else {
+            format!("{{{}}}", elements)
+        }
+    }
+
+    /// Reads `length` bytes of UTF-8 and formats them as a quoted string
+    fn format_string(&self, data: usize, length: usize) -> String {
+        match (self.read_memory)(data, length.min(MAX_ELEMENTS)) {
+            Some(bytes) => {
+                let ellipsis = if length > MAX_ELEMENTS { "..." } else { "" };
+                format!("{:?}{}", String::from_utf8_lossy(&bytes), ellipsis)
+            }
+            None => format!("<error: Cannot access memory at address {:#x}>", data),
+        }
+    }
+
+    fn format_member(&self, member: &Member, bytes: &[u8], depth: usize) -> String {
+        match self.member_bytes(member, bytes) {
+            Some((member_type, member_bytes)) => {
+                self.format_nested(member_type, member_bytes, depth + 1)
+            }
+            None => "<unknown>".to_string(),
+        }
+    }
+
+    /// The type of a member, and its bytes within those of the containing value
+    fn member_bytes<'b>(&self, member: &Member, bytes: &'b [u8]) -> Option<(&'a Type, &'b [u8])> {
+        let member_type = self.debug_data.get_type(member.type_id)?;
+        let bytes = bytes.get(member.offset..member.offset + member_type.size)?;
+        Some((member_type, bytes))
+    }
+}
+
+/// Formats the fields of a Rust struct or enum variant: `{x: 1, y: 2}` for named fields, `(1, 2)`
+/// for tuple-like ones (which DWARF names `__0`, `__1`...), and nothing if there are none
+fn rust_fields(fields: &[(&str, String)]) -> String {
+    if fields.is_empty() {
+        return String::new();
+    }
+    if fields.iter().all(|(name, _)| name.starts_with("__")) {
+        let values: Vec<&str> = fields.iter().map(|(_, value)| value.as_str()).collect();
+        return format!("({})", values.join(", "));
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}